@@ -13,6 +13,12 @@ fn main() {
     );
     println!("cargo:rustc-link-lib=dylib=QuEST");
 
+    // The `openmp` feature calls the OpenMP runtime that QuEST is built
+    // against, which is GCC's libgomp with the default toolchain.
+    if env::var("CARGO_FEATURE_OPENMP").is_ok() {
+        println!("cargo:rustc-link-lib=dylib=gomp");
+    }
+
     // See: https://github.com/rust-lang/rust-bindgen/issues/687
     let ignored_macros = IgnoreMacros(
         vec![
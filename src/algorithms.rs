@@ -0,0 +1,618 @@
+use std::f64::consts::PI;
+
+use crate::classical::ClassicalRegister;
+use crate::environment::QuestEnv;
+use crate::qubits::QuReg;
+use crate::{QReal, DEFAULT_TOLERANCE};
+
+/// Run quantum phase estimation, leaving the estimate of the phase in the
+/// counting register.
+///
+/// The counting qubits are put into superposition, then
+/// `controlled_unitary_power(qureg, control, target_qubits, k)` is called for
+/// each counting qubit `counting_qubits[k]` and must apply `U^(2^k)` to the
+/// target qubits, controlled on `control`. Finally, an inverse quantum
+/// Fourier transform is applied to the counting register.
+///
+/// If the target qubits hold an eigenstate of `U` with eigenvalue
+/// `exp(2πiφ)`, measuring `counting_qubits[j]` gives bit `j` (counting from
+/// the least significant) of the best `counting_qubits.len()`-bit
+/// approximation of `φ`.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{phase_estimation, QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(4, &env);
+/// // The target qubit 3 is in |1>, an eigenstate of the T gate with phase 1/8.
+/// qubits.init_classical_state(0b1000);
+///
+/// phase_estimation(&mut qubits, &[0, 1, 2], &[3], |qureg, control, targets, k| {
+///     let angle = std::f64::consts::PI / 4.0 * (1 << k) as f64;
+///     qureg.controlled_phase_shift(control, targets[0], angle);
+/// });
+///
+/// // 1/8 is 0b001 in three bits.
+/// assert_eq!(qubits.measure(0), 1);
+/// assert_eq!(qubits.measure(1), 0);
+/// assert_eq!(qubits.measure(2), 0);
+/// ```
+pub fn phase_estimation<'a, F>(
+    qureg: &mut QuReg<'a>,
+    counting_qubits: &[i32],
+    target_qubits: &[i32],
+    mut controlled_unitary_power: F,
+) where
+    F: FnMut(&mut QuReg<'a>, i32, &[i32], u32),
+{
+    if let Some(qubit) = counting_qubits
+        .iter()
+        .find(|qubit| target_qubits.contains(qubit))
+    {
+        panic!(
+            "Qubit {} can't be both a counting qubit and a target qubit.",
+            qubit
+        );
+    }
+
+    for &qubit in counting_qubits {
+        qureg.hadamard(qubit);
+    }
+    for (k, &control_qubit) in counting_qubits.iter().enumerate() {
+        controlled_unitary_power(qureg, control_qubit, target_qubits, k as u32);
+    }
+    qureg.apply_inverse_qft(counting_qubits);
+}
+
+/// A callback given the number of iterations run so far and the register.
+pub type Progress<'p> = dyn FnMut(usize, &QuReg) + 'p;
+
+/// One of the operations chosen between by `select`.
+pub type Branch<'b> = Box<dyn Fn(&mut QuReg) + 'b>;
+
+/// Run Grover's search over `num_qubits` qubits and return the index of the
+/// most probable basis state at the end.
+///
+/// `oracle` must flip the phase of the marked basis states. If `iterations`
+/// is `None`, the optimal number for a single marked state,
+/// `floor(π/4 * sqrt(2^num_qubits))`, is used. If given, `progress` is called
+/// after every iteration with the number of iterations so far and the
+/// register, e.g. to monitor the probability of a solution.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{grover_search, QuestEnv};
+///
+/// let env = QuestEnv::new();
+/// // Mark |101> by flipping its phase with X gates around a CCZ.
+/// let found = grover_search(&env, 3, |qureg| {
+///     qureg.pauli_x(1).multi_controlled_z(&[0, 1, 2]).pauli_x(1);
+/// }, None, None);
+/// assert_eq!(found, 0b101);
+/// ```
+pub fn grover_search<F>(
+    env: &QuestEnv,
+    num_qubits: i32,
+    oracle: F,
+    iterations: Option<usize>,
+    mut progress: Option<&mut Progress<'_>>,
+) -> i64
+where
+    F: Fn(&mut QuReg),
+{
+    let num_states = 1i64 << num_qubits;
+    let iterations =
+        iterations.unwrap_or_else(|| (PI / 4.0 * (num_states as f64).sqrt()).floor() as usize);
+    let qubits: Vec<i32> = (0..num_qubits).collect();
+
+    let mut qureg = QuReg::new(num_qubits, env);
+    qureg.init_plus_state();
+    for iteration in 1..=iterations {
+        oracle(&mut qureg);
+        qureg.apply_grover_diffuser(&qubits);
+        if let Some(progress) = progress.as_mut() {
+            progress(iteration, &qureg);
+        }
+    }
+
+    qureg.most_probable_state().0
+}
+
+/// Run amplitude amplification, of which Grover's search is the special case
+/// where `A` is a Hadamard on every qubit.
+///
+/// The register is reset to `A|0>` with `state_prep`, then the operator
+/// `A S_0 A† S_χ` is applied `iterations` times, where `oracle` applies `S_χ`
+/// by flipping the phase of the marked states and `S_0` flips the phase of
+/// `|0...0>`. This is the usual `Q = -A S_0 A† S_χ` up to a global phase of
+/// -1, which no probability depends on. QuEST can't invert an arbitrary
+/// closure, so `A†` must be given explicitly as `inverse_state_prep`.
+///
+/// If `A|0>` has overlap `sin(θ)` with the marked states, after `k`
+/// iterations their total probability is `sin²((2k + 1)θ)`. As in
+/// `grover_search`, `progress` is called after every iteration.
+pub fn amplitude_amplify<'a, O, P, U>(
+    qureg: &mut QuReg<'a>,
+    oracle: O,
+    state_prep: P,
+    inverse_state_prep: U,
+    iterations: usize,
+    mut progress: Option<&mut Progress<'_>>,
+) where
+    O: Fn(&mut QuReg<'a>),
+    P: Fn(&mut QuReg<'a>),
+    U: Fn(&mut QuReg<'a>),
+{
+    let qubits: Vec<i32> = (0..qureg.num_qubits()).collect();
+
+    qureg.init_zero_state();
+    state_prep(qureg);
+    for iteration in 1..=iterations {
+        oracle(qureg);
+        inverse_state_prep(qureg);
+        qureg.flip_zero_state_phase(&qubits);
+        state_prep(qureg);
+        if let Some(progress) = progress.as_mut() {
+            progress(iteration, qureg);
+        }
+    }
+}
+
+/// Estimate the overlap `|<a|b>|²` of two state vectors with the SWAP test,
+/// from `shots` measurements of the ancilla.
+///
+/// A register holding an ancilla and copies of both states is built, and the
+/// ancilla is put through a Hadamard, a controlled swap of the two states and
+/// another Hadamard. It is then 0 with probability `(1 + |<a|b>|²) / 2`. The
+/// estimate is clamped to `[0, 1]`, since shot noise can push it outside.
+pub fn swap_test(env: &QuestEnv, state_a: &QuReg, state_b: &QuReg, shots: usize) -> QReal {
+    let num_qubits = state_a.num_qubits();
+    if state_a.is_density_matrix() || state_b.is_density_matrix() {
+        panic!("The SWAP test can only be run on state vectors.");
+    }
+    if state_b.num_qubits() != num_qubits {
+        panic!("The SWAP test needs two states with the same number of qubits.");
+    }
+    if shots == 0 {
+        panic!("The SWAP test needs at least one shot.");
+    }
+
+    // Qubit 0 is the ancilla, followed by the qubits of a and then of b.
+    let mut ancilla = QuReg::new(1, env);
+    ancilla.init_zero_state();
+    let mut circuit = QuReg::new(2 * num_qubits + 1, env);
+    circuit.tensor_from(&[&ancilla, state_a, state_b]);
+
+    circuit.hadamard(0);
+    for qubit in 1..=num_qubits {
+        circuit.controlled_swap(0, qubit, qubit + num_qubits);
+    }
+    circuit.hadamard(0);
+
+    let mut shot = QuReg::new(2 * num_qubits + 1, env);
+    let num_zeros = (0..shots)
+        .filter(|_| {
+            circuit.clone_into(&mut shot);
+            shot.measure(0) == 0
+        })
+        .count();
+    let overlap = 2.0 * num_zeros as QReal / shots as QReal - 1.0;
+    overlap.clamp(0.0, 1.0)
+}
+
+/// Apply `unitaries[k]` to the part of the state in which the ancilla qubits
+/// hold `k`, with `ancilla_qubits[0]` as its least significant bit. Ancilla
+/// values without a unitary are left alone. This is the SELECT step of a
+/// linear combination of unitaries.
+///
+/// Each closure runs on a copy of the whole register and must not act on
+/// the ancilla qubits. Only state vectors are supported, since a density
+/// matrix would also mix the branches of its rows and columns.
+pub fn select(qureg: &mut QuReg, ancilla_qubits: &[i32], unitaries: &[Branch<'_>]) {
+    if qureg.is_density_matrix() {
+        panic!("Select can only be applied to a state vector.");
+    }
+    if unitaries.len() > 1 << ancilla_qubits.len() {
+        panic!(
+            "{} ancilla qubits can only select between {} unitaries, not {}.",
+            ancilla_qubits.len(),
+            1 << ancilla_qubits.len(),
+            unitaries.len()
+        );
+    }
+
+    let ancilla_value = |index: i64| {
+        ancilla_qubits
+            .iter()
+            .enumerate()
+            .fold(0, |value, (bit, &qubit)| {
+                value | ((index >> qubit) & 1) << bit
+            }) as usize
+    };
+    let dim = 1i64 << qureg.num_qubits();
+    let (mut reals, mut imags): (Vec<_>, Vec<_>) = (0..dim)
+        .map(|index| {
+            let amplitude = qureg.amplitude(index);
+            (amplitude.real, amplitude.imag)
+        })
+        .unzip();
+
+    // The unitaries leave the ancilla alone, so each branch of the state
+    // evolves independently and can be read off its own copy.
+    let mut branch = qureg.clone();
+    for (k, unitary) in unitaries.iter().enumerate() {
+        qureg.clone_into(&mut branch);
+        unitary(&mut branch);
+        for index in (0..dim).filter(|&index| ancilla_value(index) == k) {
+            let amplitude = branch.amplitude(index);
+            reals[index as usize] = amplitude.real;
+            imags[index as usize] = amplitude.imag;
+        }
+    }
+    qureg.init_state_from_amplitudes(reals, imags);
+}
+
+/// Teleport the state of `msg_qubit` onto `bob_qubit`, returning the
+/// outcomes of measuring `msg_qubit` and `alice_qubit` as bits 0 and 1.
+///
+/// Alice's and Bob's qubits must start in |0>. They are entangled into a
+/// Bell pair, the message and Alice's qubit are measured in the Bell basis,
+/// and Bob's qubit is corrected with X and Z gates conditioned on the results.
+pub fn teleport(
+    qureg: &mut QuReg,
+    msg_qubit: i32,
+    alice_qubit: i32,
+    bob_qubit: i32,
+) -> ClassicalRegister {
+    qureg
+        .hadamard(alice_qubit)
+        .controlled_not(alice_qubit, bob_qubit)
+        .controlled_not(msg_qubit, alice_qubit)
+        .hadamard(msg_qubit);
+
+    let mut bits = ClassicalRegister::new(2);
+    qureg.measure_into(msg_qubit, &mut bits, 0);
+    qureg.measure_into(alice_qubit, &mut bits, 1);
+    qureg.x_if(&bits, 1, bob_qubit).z_if(&bits, 0, bob_qubit);
+    bits
+}
+
+/// Estimate the derivative of an expectation value with respect to
+/// `params[param_index]` with the parameter-shift rule,
+/// `(E(θ + s) - E(θ - s)) / (2 sin s)`, where `E(θ)` is `observable` of the
+/// state `build` prepares from `params` on a `num_qubits` register in |0...0>.
+///
+/// The rule is exact when the parameter only enters as the angle of gates
+/// generated by a Pauli operator, such as `rotate_y` or `multi_rotate_pauli`,
+/// and is usually used with `shift = π/2`.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{parameter_shift_gradient, QuestEnv};
+///
+/// let env = QuestEnv::new();
+/// let theta = 0.6;
+/// let gradient = parameter_shift_gradient(
+///     &env,
+///     1,
+///     |params, qureg| {
+///         qureg.rotate_y(0, params[0]);
+///     },
+///     &[theta],
+///     |qureg| qureg.bloch_vector(0).z,
+///     0,
+///     std::f64::consts::PI / 2.0,
+/// );
+/// // <Z> = cos(θ), so its derivative is -sin(θ).
+/// assert!((gradient + theta.sin()).abs() < 1e-10);
+/// ```
+pub fn parameter_shift_gradient<B, O>(
+    env: &QuestEnv,
+    num_qubits: i32,
+    build: B,
+    params: &[QReal],
+    observable: O,
+    param_index: usize,
+    shift: QReal,
+) -> QReal
+where
+    B: Fn(&[QReal], &mut QuReg),
+    O: Fn(&QuReg) -> QReal,
+{
+    if param_index >= params.len() {
+        panic!(
+            "Parameter {} is outside of the {} parameters.",
+            param_index,
+            params.len()
+        );
+    }
+    if shift.sin().abs() < DEFAULT_TOLERANCE {
+        panic!(
+            "Shift {} is a multiple of π, so can't give a gradient.",
+            shift
+        );
+    }
+
+    let mut qureg = QuReg::new(num_qubits, env);
+    let mut shifted = params.to_vec();
+    let mut expectation = |param: QReal| {
+        shifted[param_index] = param;
+        qureg.init_zero_state();
+        build(&shifted, &mut qureg);
+        observable(&qureg)
+    };
+
+    let param = params[param_index];
+    (expectation(param + shift) - expectation(param - shift)) / (2.0 * shift.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{
+        amplitude_amplify, grover_search, parameter_shift_gradient, phase_estimation, select,
+        swap_test, teleport, Branch,
+    };
+    use crate::{assert_qureg_close, ComplexMatrix2, QReal, QuReg, QuestEnv};
+
+    fn estimate_z_phase(target_state: i64) -> (i32, i32, i32) {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        qubits.init_classical_state(target_state << 3);
+
+        phase_estimation(
+            &mut qubits,
+            &[0, 1, 2],
+            &[3],
+            |qureg, control, targets, k| {
+                // Z^(2^k) is Z for k = 0 and the identity otherwise.
+                if k == 0 {
+                    qureg.controlled_phase_flip(control, targets[0]);
+                }
+            },
+        );
+
+        (qubits.measure(0), qubits.measure(1), qubits.measure(2))
+    }
+
+    #[test]
+    fn phase_estimation_of_z_eigenstates() {
+        // Z|1> = exp(2πi * 1/2)|1>, and 1/2 is 0b100 in three bits.
+        assert_eq!(estimate_z_phase(1), (0, 0, 1));
+        // Z|0> = |0>, with zero phase.
+        assert_eq!(estimate_z_phase(0), (0, 0, 0));
+    }
+
+    #[test]
+    fn phase_estimation_of_general_phase() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        qubits.init_classical_state(0b1000);
+
+        // Phase 5/8 = 0b101 in three bits.
+        let phase = 5.0 / 8.0;
+        phase_estimation(
+            &mut qubits,
+            &[0, 1, 2],
+            &[3],
+            |qureg, control, targets, k| {
+                let angle = 2.0 * std::f64::consts::PI * phase * (1 << k) as f64;
+                qureg.controlled_phase_shift(control, targets[0], angle);
+            },
+        );
+
+        assert_eq!(
+            (qubits.measure(0), qubits.measure(1), qubits.measure(2)),
+            (1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn grover_search_finds_marked_element() {
+        let env = QuestEnv::new();
+        for &marked in &[0b0000, 0b0110, 0b1011] {
+            let found = grover_search(
+                &env,
+                4,
+                |qureg| {
+                    let flips: Vec<i32> = (0..4).filter(|q| marked >> q & 1 == 0).collect();
+                    for &qubit in &flips {
+                        qureg.pauli_x(qubit);
+                    }
+                    qureg.multi_controlled_z(&[0, 1, 2, 3]);
+                    for &qubit in &flips {
+                        qureg.pauli_x(qubit);
+                    }
+                },
+                None,
+                None,
+            );
+            assert_eq!(found, marked);
+        }
+    }
+
+    #[test]
+    fn amplitude_amplification_of_biased_state() {
+        let env = QuestEnv::new();
+        let mark_all_ones = |qureg: &mut QuReg| {
+            qureg.multi_controlled_z(&[0, 1, 2]);
+        };
+
+        // Each qubit is 1 with probability 0.7, so |111> starts with
+        // probability 0.343 rather than the 0.125 of a uniform state.
+        let angle = 2.0 * (0.7 as QReal).sqrt().asin();
+        let mut biased = QuReg::new(3, &env);
+        amplitude_amplify(
+            &mut biased,
+            mark_all_ones,
+            |qureg| {
+                qureg.apply_to_all(|qureg, qubit| {
+                    qureg.rotate_y(qubit, angle);
+                });
+            },
+            |qureg| {
+                qureg.apply_to_all(|qureg, qubit| {
+                    qureg.rotate_y(qubit, -angle);
+                });
+            },
+            1,
+            None,
+        );
+
+        let hadamards = |qureg: &mut QuReg| {
+            qureg.hadamard_all();
+        };
+        let mut uniform = QuReg::new(3, &env);
+        amplitude_amplify(&mut uniform, mark_all_ones, hadamards, hadamards, 1, None);
+
+        let theta = (0.343 as QReal).sqrt().asin();
+        let expected = (3.0 * theta).sin().powi(2);
+        let amplified = biased.probability_of_state(0b111);
+        assert!((amplified - expected).abs() < 1e-10);
+        assert!(amplified > uniform.probability_of_state(0b111));
+    }
+
+    #[test]
+    fn grover_progress_is_reported_every_iteration() {
+        let env = QuestEnv::new();
+        let marked = 0b011010;
+        let mut probabilities = Vec::new();
+        let mut record = |iteration: usize, qureg: &QuReg| {
+            assert_eq!(iteration, probabilities.len() + 1);
+            probabilities.push(qureg.probability_of_state(marked));
+        };
+
+        let found = grover_search(
+            &env,
+            6,
+            |qureg| {
+                let flips: Vec<i32> = (0..6).filter(|q| marked >> q & 1 == 0).collect();
+                for &qubit in &flips {
+                    qureg.pauli_x(qubit);
+                }
+                qureg.multi_controlled_z(&[0, 1, 2, 3, 4, 5]);
+                for &qubit in &flips {
+                    qureg.pauli_x(qubit);
+                }
+            },
+            None,
+            Some(&mut record),
+        );
+
+        assert_eq!(found, marked);
+        assert_eq!(probabilities.len(), 6);
+        assert!(probabilities.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+
+    #[test]
+    fn swap_test_estimates_overlap() {
+        let env = QuestEnv::new();
+        let mut a = QuReg::new(2, &env);
+        let mut b = QuReg::new(2, &env);
+        a.init_zero_state().rotate_y(0, 0.8).hadamard(1);
+        a.clone_into(&mut b);
+        assert_eq!(swap_test(&env, &a, &b, 200), 1.0);
+
+        b.init_zero_state()
+            .rotate_y(0, 0.8 + std::f64::consts::PI)
+            .hadamard(1);
+        assert!(swap_test(&env, &a, &b, 4000) < 0.1);
+
+        // |<0|+>|² = 1/2.
+        let mut zero = QuReg::new(1, &env);
+        let mut plus = QuReg::new(1, &env);
+        zero.init_zero_state();
+        plus.init_plus_state();
+        assert!((swap_test(&env, &zero, &plus, 4000) - 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn select_applies_each_unitary_to_its_branch() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_zero_state().hadamard(0).rotate_y(1, 0.5);
+        let mut expected = qubits.clone();
+
+        let unitaries: Vec<Branch> = vec![
+            Box::new(|qureg| {
+                qureg.pauli_x(1);
+            }),
+            Box::new(|qureg| {
+                qureg.hadamard(1);
+            }),
+        ];
+        select(&mut qubits, &[0], &unitaries);
+
+        let hadamard = (0.5 as QReal).sqrt();
+        expected
+            .pauli_x(0)
+            .controlled_not(0, 1)
+            .pauli_x(0)
+            .controlled_unitary(
+                0,
+                1,
+                ComplexMatrix2::real([[hadamard, hadamard], [hadamard, -hadamard]]),
+            );
+        assert_qureg_close(&qubits, &expected, 1e-10);
+    }
+
+    #[test]
+    fn teleport_random_state() {
+        let env = QuestEnv::new();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..5 {
+            let unitary = ComplexMatrix2::random(&mut rng);
+            let mut message = QuReg::new(1, &env);
+            message.init_zero_state().unitary(0, unitary);
+
+            let mut qubits = QuReg::new(3, &env);
+            qubits.init_zero_state().unitary(0, unitary);
+            let bits = teleport(&mut qubits, 0, 1, 2);
+            assert_eq!(bits.num_bits(), 2);
+
+            let bob = qubits.reduced_density_matrix(&[2], &env);
+            assert!((bob.calculate_fidelity(&message) - 1.0).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "is a multiple of π")]
+    fn parameter_shift_gradient_rejects_shift_of_pi() {
+        let env = QuestEnv::new();
+        parameter_shift_gradient(
+            &env,
+            1,
+            |params, qureg| {
+                qureg.rotate_y(0, params[0]);
+            },
+            &[0.3],
+            |qureg| qureg.bloch_vector(0).z,
+            0,
+            std::f64::consts::PI,
+        );
+    }
+
+    #[test]
+    fn parameter_shift_gradient_of_rotate_y() {
+        let env = QuestEnv::new();
+        for &theta in &[0.0, 0.4, 1.3, 2.9] {
+            for &shift in &[std::f64::consts::PI / 2.0, 0.3] {
+                let gradient = parameter_shift_gradient(
+                    &env,
+                    2,
+                    |params, qureg| {
+                        qureg.hadamard(1).rotate_y(0, params[1]);
+                    },
+                    &[0.8, theta],
+                    |qureg| qureg.bloch_vector(0).z,
+                    1,
+                    shift,
+                );
+                assert!((gradient + theta.sin()).abs() < 1e-10);
+            }
+        }
+    }
+}
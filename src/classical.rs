@@ -0,0 +1,75 @@
+/// Classical bits recording measurement outcomes, so that later gates can be
+/// conditioned on them with e.g. `QuReg::x_if`.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{ClassicalRegister, QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(2, &env);
+/// let mut bits = ClassicalRegister::new(1);
+///
+/// qubits.init_classical_state(0b01);
+/// qubits.measure_into(0, &mut bits, 0);
+/// qubits.x_if(&bits, 0, 1);
+/// assert_eq!(qubits.probability_of_state(0b11), 1.0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassicalRegister {
+    bits: Vec<i32>,
+}
+
+impl ClassicalRegister {
+    /// Create a register of `num_bits` bits, all 0.
+    pub fn new(num_bits: usize) -> Self {
+        ClassicalRegister {
+            bits: vec![0; num_bits],
+        }
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn bits(&self) -> &[i32] {
+        &self.bits
+    }
+
+    pub fn get(&self, bit: usize) -> i32 {
+        self.check_bit(bit);
+        self.bits[bit]
+    }
+
+    pub fn set(&mut self, bit: usize, value: i32) -> &mut Self {
+        self.check_bit(bit);
+        if value != 0 && value != 1 {
+            panic!("Classical bit value {} is not 0 or 1.", value);
+        }
+        self.bits[bit] = value;
+        self
+    }
+
+    fn check_bit(&self, bit: usize) {
+        if bit >= self.bits.len() {
+            panic!(
+                "Bit {} is outside of a classical register of {} bits.",
+                bit,
+                self.bits.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClassicalRegister;
+
+    #[test]
+    fn set_and_get_bits() {
+        let mut bits = ClassicalRegister::new(3);
+        bits.set(0, 1).set(2, 1).set(0, 0);
+        assert_eq!(bits.bits(), &[0, 0, 1]);
+        assert_eq!(bits.get(2), 1);
+        assert_eq!(bits.num_bits(), 3);
+    }
+}
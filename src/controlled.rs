@@ -0,0 +1,182 @@
+use crate::qubits::QuReg;
+use crate::{Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, QReal, Vector};
+
+/// A view of a register that applies every gate conditioned on a single
+/// control qubit, created with `QuReg::with_control`.
+///
+/// Each method forwards to the matching controlled gate in QuEST, so
+/// `qubits.with_control(0).x(1)` is the same as `qubits.controlled_not(0, 1)`.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(3, &env);
+/// qubits.init_zero_state().hadamard(0);
+/// qubits.with_control(0).x(1).h(2).rotate_y(2, 0.3);
+/// ```
+pub struct ControlledQuReg<'q, 'a> {
+    qureg: &'q mut QuReg<'a>,
+    control_qubit: i32,
+}
+
+impl<'q, 'a> ControlledQuReg<'q, 'a> {
+    pub(crate) fn new(qureg: &'q mut QuReg<'a>, control_qubit: i32) -> Self {
+        ControlledQuReg {
+            qureg,
+            control_qubit,
+        }
+    }
+
+    pub fn control_qubit(&self) -> i32 {
+        self.control_qubit
+    }
+
+    pub fn x(&mut self, target_qubit: i32) -> &mut Self {
+        self.qureg.controlled_not(self.control_qubit, target_qubit);
+        self
+    }
+
+    pub fn y(&mut self, target_qubit: i32) -> &mut Self {
+        self.qureg
+            .controlled_pauli_y(self.control_qubit, target_qubit);
+        self
+    }
+
+    pub fn z(&mut self, target_qubit: i32) -> &mut Self {
+        self.qureg
+            .controlled_phase_flip(self.control_qubit, target_qubit);
+        self
+    }
+
+    pub fn h(&mut self, target_qubit: i32) -> &mut Self {
+        let value = std::f64::consts::FRAC_1_SQRT_2 as QReal;
+        let hadamard = ComplexMatrix2::real([[value, value], [value, -value]]);
+        self.unitary(target_qubit, hadamard)
+    }
+
+    pub fn phase_shift(&mut self, target_qubit: i32, angle: QReal) -> &mut Self {
+        self.qureg
+            .controlled_phase_shift(self.control_qubit, target_qubit, angle);
+        self
+    }
+
+    pub fn rotate_x(&mut self, target_qubit: i32, angle: QReal) -> &mut Self {
+        self.qureg
+            .controlled_rotate_x(self.control_qubit, target_qubit, angle);
+        self
+    }
+
+    pub fn rotate_y(&mut self, target_qubit: i32, angle: QReal) -> &mut Self {
+        self.qureg
+            .controlled_rotate_y(self.control_qubit, target_qubit, angle);
+        self
+    }
+
+    pub fn rotate_z(&mut self, target_qubit: i32, angle: QReal) -> &mut Self {
+        self.qureg
+            .controlled_rotate_z(self.control_qubit, target_qubit, angle);
+        self
+    }
+
+    pub fn rotate_around_axis(
+        &mut self,
+        target_qubit: i32,
+        angle: QReal,
+        axis: Vector,
+    ) -> &mut Self {
+        self.qureg
+            .controlled_rotate_around_axis(self.control_qubit, target_qubit, angle, axis);
+        self
+    }
+
+    pub fn unitary(&mut self, target_qubit: i32, unitary_matrix: ComplexMatrix2) -> &mut Self {
+        self.qureg
+            .controlled_unitary(self.control_qubit, target_qubit, unitary_matrix);
+        self
+    }
+
+    pub fn compact_unitary(
+        &mut self,
+        target_qubit: i32,
+        alpha: Complex,
+        beta: Complex,
+    ) -> &mut Self {
+        self.qureg
+            .controlled_compact_unitary(self.control_qubit, target_qubit, alpha, beta);
+        self
+    }
+
+    pub fn two_qubit_unitary(
+        &mut self,
+        target_qubit_one: i32,
+        target_qubit_two: i32,
+        unitary_matrix: ComplexMatrix4,
+    ) -> &mut Self {
+        self.qureg.controlled_two_qubit_unitary(
+            self.control_qubit,
+            target_qubit_one,
+            target_qubit_two,
+            unitary_matrix,
+        );
+        self
+    }
+
+    pub fn multi_qubit_unitary(
+        &mut self,
+        target_qubits: Vec<i32>,
+        unitary_matrix: ComplexMatrixN,
+    ) -> &mut Self {
+        self.qureg.controlled_multi_qubit_unitary(
+            self.control_qubit,
+            target_qubits,
+            unitary_matrix,
+        );
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assert_qureg_close, QuReg, QuestEnv};
+
+    fn prepare(qubits: &mut QuReg) {
+        qubits
+            .init_zero_state()
+            .hadamard(0)
+            .rotate_y(1, 0.4)
+            .rotate_x(2, 1.1);
+    }
+
+    #[test]
+    fn with_control_x_is_controlled_not() {
+        let env = QuestEnv::new();
+        let mut expected = QuReg::new(3, &env);
+        let mut actual = QuReg::new(3, &env);
+        prepare(&mut expected);
+        prepare(&mut actual);
+
+        expected.controlled_not(0, 1);
+        actual.with_control(0).x(1);
+
+        assert_qureg_close(&expected, &actual, 1e-10);
+    }
+
+    #[test]
+    fn with_control_chains_controlled_gates() {
+        let env = QuestEnv::new();
+        let mut expected = QuReg::new(3, &env);
+        let mut actual = QuReg::new(3, &env);
+        prepare(&mut expected);
+        prepare(&mut actual);
+
+        expected
+            .controlled_rotate_y(0, 2, 0.7)
+            .controlled_phase_flip(0, 1)
+            .controlled_pauli_y(0, 2);
+        actual.with_control(0).rotate_y(2, 0.7).z(1).y(2);
+
+        assert_qureg_close(&expected, &actual, 1e-10);
+    }
+}
@@ -1,15 +1,117 @@
+#[cfg(feature = "openmp")]
+use std::os::raw::c_int;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
 use crate::ffi;
+use crate::qubits::QuReg;
+
+// QuEST's multithreaded build runs on OpenMP, but doesn't expose any control
+// over it, so the OpenMP runtime is called directly.
+#[cfg(feature = "openmp")]
+extern "C" {
+    fn omp_set_num_threads(num_threads: c_int);
+    fn omp_get_max_threads() -> c_int;
+}
+
+// QuEST draws measurement outcomes from a single global Mersenne Twister,
+// which isn't safe to use from several threads at once, so every call that
+// seeds or draws from it holds this lock.
+static RNG_LOCK: Mutex<()> = Mutex::new(());
+
+pub(crate) fn lock_rng() -> MutexGuard<'static, ()> {
+    RNG_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Which parallel backends the QuEST library this crate links against was
+/// compiled with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub mpi_enabled: bool,
+    pub gpu_enabled: bool,
+    pub openmp_enabled: bool,
+}
+
+impl Capabilities {
+    // QuEST describes its build as e.g. "3qubits CPU OMP=1 threads=8", with
+    // " ranks=4" appended by the distributed build, or "3qubits GPU noMpi
+    // noOMP" for the GPU build.
+    fn from_environment_string(description: &str) -> Self {
+        Capabilities {
+            mpi_enabled: description.contains("ranks="),
+            gpu_enabled: description.contains("GPU"),
+            openmp_enabled: description.contains("OMP=1"),
+        }
+    }
+}
 
 /// QuEST Environment
+///
+/// The environment is `Send` and `Sync`, so it can be shared between the
+/// threads that own its registers, e.g. with `std::thread::scope` or an
+/// `Arc`. In an MPI build, QuEST only initialises MPI for the main thread,
+/// and in a GPU build, device memory belongs to the thread that allocated
+/// it, so registers of those environments must stay on the main thread.
 pub struct QuestEnv {
     env: ffi::QuESTEnv,
+    memory_limit: Option<u64>,
+    profiling: bool,
 }
 
 impl QuestEnv {
     pub fn new() -> Self {
+        // Creating an environment reseeds QuEST's generator from the time and
+        // pid.
+        let _rng = lock_rng();
+        Self::create()
+    }
+
+    /// Create an environment and seed QuEST's random number generator with
+    /// `seed`, before anything else can draw from or reseed it.
+    ///
+    /// The generator is shared by the whole process. It is drawn from by
+    /// `QuReg::measure` and everything built on it: `measure_with_stats`,
+    /// `measure_outcome`, `measure_into`, `measure_qubits`,
+    /// `measure_all_with_stats`, `swap_test` and `teleport`. A measurement
+    /// whose outcome is certain draws nothing. Creating another environment
+    /// with `QuestEnv::new` reseeds it from the time and pid, and
+    /// `seed_quest` and `seed_quest_default` reseed it explicitly. Nothing
+    /// else uses it, and `measure_with_rng` and `init_random_pure_state`
+    /// draw from the generator they're given instead.
+    ///
+    /// So runs from the same seed only match if they make the same calls
+    /// in the same order, without other threads using QuEST's generator
+    /// meanwhile.
+    ///
+    /// ## Examples
+    /// ```
+    /// use quest_rs::{QuestEnv, QuReg};
+    ///
+    /// fn run(env: &QuestEnv) -> Vec<i32> {
+    ///     let mut qubits = QuReg::new(8, env);
+    ///     qubits.hadamard_all();
+    ///     qubits.measure_qubits(&[0, 1, 2, 3, 4, 5, 6, 7])
+    /// }
+    ///
+    /// let first = run(&QuestEnv::with_seed(&[1, 2, 3]));
+    /// let second = run(&QuestEnv::with_seed(&[1, 2, 3]));
+    /// assert_eq!(first, second);
+    /// ```
+    pub fn with_seed(seed: &[u64]) -> Self {
+        let _rng = lock_rng();
+        let env = Self::create();
+        unsafe {
+            ffi::seedQuEST(seed.as_ptr() as *mut u64, seed.len() as i32);
+        }
+        env
+    }
+
+    // The caller must hold `lock_rng`.
+    fn create() -> Self {
         unsafe {
             QuestEnv {
                 env: ffi::createQuESTEnv(),
+                memory_limit: None,
+                profiling: false,
             }
         }
     }
@@ -25,6 +127,60 @@ impl QuestEnv {
             ffi::reportQuESTEnv(self.env);
         }
     }
+
+    /// Limit how many bytes of state a register created with
+    /// `QuReg::try_new` or `QuReg::try_new_density` may use. There is no
+    /// limit by default.
+    pub fn set_memory_limit(&mut self, limit_bytes: u64) -> &mut Self {
+        self.memory_limit = Some(limit_bytes);
+        self
+    }
+
+    pub fn memory_limit(&self) -> Option<u64> {
+        self.memory_limit
+    }
+
+    /// Record the time spent in each gate applied to this environment's
+    /// registers, to be read with `QuReg::profile_report`.
+    pub fn enable_profiling(&mut self) -> &mut Self {
+        self.profiling = true;
+        self
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiling
+    }
+
+    /// Which of MPI, a GPU and OpenMP QuEST was built to use.
+    ///
+    /// QuEST only reports this through a register, so a one-qubit register is
+    /// briefly created.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_environment_string(&QuReg::new(1, self).environment_string())
+    }
+
+    /// Set how many OpenMP threads this process uses to simulate registers.
+    /// In a hybrid MPI and OpenMP setup, this only affects the calling rank.
+    ///
+    /// Without the `openmp` feature, the thread count can still be set with
+    /// the `OMP_NUM_THREADS` environment variable before the program starts.
+    #[cfg(feature = "openmp")]
+    pub fn set_num_threads(&mut self, num_threads: i32) -> &mut Self {
+        if num_threads < 1 {
+            panic!("Number of threads must be at least 1, not {}.", num_threads);
+        }
+
+        unsafe {
+            omp_set_num_threads(num_threads);
+        }
+        self
+    }
+
+    /// How many OpenMP threads this process will use to simulate registers.
+    #[cfg(feature = "openmp")]
+    pub fn num_threads(&self) -> i32 {
+        unsafe { omp_get_max_threads() }
+    }
 }
 
 impl Default for QuestEnv {
@@ -53,7 +209,10 @@ impl From<&QuestEnv> for ffi::QuESTEnv {
     }
 }
 
+/// Reseed QuEST's random number generator. See `QuestEnv::with_seed`
+/// for what draws from it.
 pub fn seed_quest(seed_values: Vec<u64>) {
+    let _rng = lock_rng();
     unsafe {
         ffi::seedQuEST(seed_values.as_ptr() as *mut u64, seed_values.len() as i32);
     }
@@ -71,7 +230,46 @@ pub fn seed_quest(seed_values: Vec<u64>) {
 /// For more information about the MT, see:
 /// http://www.math.sci.hiroshima-u.ac.jp/~m-mat/MT/MT2002/emt19937ar.html.
 pub fn seed_quest_default() {
+    let _rng = lock_rng();
     unsafe {
         ffi::seedQuESTDefault();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, QuestEnv};
+
+    #[test]
+    fn capabilities_match_build() {
+        let env = QuestEnv::new();
+        let capabilities = env.capabilities();
+        // The build script uses QuEST's default CMake options, which build
+        // for the CPU without MPI, and with OpenMP if the compiler has it.
+        assert!(!capabilities.mpi_enabled);
+        assert!(!capabilities.gpu_enabled);
+        if cfg!(feature = "openmp") {
+            assert!(capabilities.openmp_enabled);
+        }
+    }
+
+    #[test]
+    fn capabilities_of_each_backend() {
+        let distributed =
+            Capabilities::from_environment_string("3qubits CPU OMP=0 threads=1 ranks=4");
+        assert!(distributed.mpi_enabled && !distributed.gpu_enabled && !distributed.openmp_enabled);
+
+        let gpu = Capabilities::from_environment_string("3qubits GPU noMpi noOMP");
+        assert!(!gpu.mpi_enabled && gpu.gpu_enabled && !gpu.openmp_enabled);
+    }
+
+    #[cfg(feature = "openmp")]
+    #[test]
+    fn num_threads_matches_set_value() {
+        let mut env = QuestEnv::new();
+        env.set_num_threads(2);
+        assert_eq!(env.num_threads(), 2);
+        env.set_num_threads(1);
+        assert_eq!(env.num_threads(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fmt;
+
+/// Errors caught by the wrapper before handing an invalid request to QuEST,
+/// which would otherwise abort the whole process.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuestError {
+    /// Allocating the register would need more memory than the limit set on
+    /// the environment.
+    MemoryLimitExceeded {
+        required_bytes: u64,
+        limit_bytes: u64,
+    },
+    /// A register needs at least one qubit.
+    InvalidQubitCount { num_qubits: i32 },
+    /// The register is too large to ever be allocated on this platform.
+    AllocationTooLarge { required_bytes: u64 },
+    /// A noise channel was applied to a state vector rather than a density
+    /// matrix.
+    RequiresDensityMatrix,
+    /// A qubit was given as both a control and a target of the same gate.
+    ControlTargetOverlap { qubit: i32 },
+    /// A qubit index is outside of the register.
+    QubitOutOfRange { qubit: i32, num_qubits: i32 },
+    /// The same qubit was given more than once as a target.
+    DuplicateQubit { qubit: i32 },
+    /// A multi-qubit matrix doesn't have one row per basis state of the
+    /// qubits it acts on.
+    MatrixSizeMismatch {
+        matrix_dim: usize,
+        num_target_qubits: usize,
+    },
+}
+
+impl fmt::Display for QuestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuestError::MemoryLimitExceeded {
+                required_bytes,
+                limit_bytes,
+            } => write!(
+                f,
+                "Register needs {} bytes but the memory limit is {} bytes",
+                required_bytes, limit_bytes
+            ),
+            QuestError::InvalidQubitCount { num_qubits } => {
+                write!(f, "A register needs at least 1 qubit, not {}", num_qubits)
+            }
+            QuestError::AllocationTooLarge { required_bytes } => write!(
+                f,
+                "Register needs {} bytes, which is too large to allocate",
+                required_bytes
+            ),
+            QuestError::RequiresDensityMatrix => {
+                write!(f, "Register must be a density matrix, not a state vector")
+            }
+            QuestError::ControlTargetOverlap { qubit } => {
+                write!(f, "Qubit {} is used as both a control and a target", qubit)
+            }
+            QuestError::QubitOutOfRange { qubit, num_qubits } => write!(
+                f,
+                "Qubit {} is outside of a register of {} qubits",
+                qubit, num_qubits
+            ),
+            QuestError::DuplicateQubit { qubit } => {
+                write!(f, "Qubit {} is given more than once", qubit)
+            }
+            QuestError::MatrixSizeMismatch {
+                matrix_dim,
+                num_target_qubits,
+            } => {
+                let expected_dim = match 1u64.checked_shl(*num_target_qubits as u32) {
+                    Some(dim) => dim.to_string(),
+                    None => format!("2^{}", num_target_qubits),
+                };
+                write!(
+                    f,
+                    "Matrix is {}x{} but {} target qubits need a {}x{} matrix",
+                    matrix_dim, matrix_dim, num_target_qubits, expected_dim, expected_dim
+                )
+            }
+        }
+    }
+}
+
+impl Error for QuestError {}
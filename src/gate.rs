@@ -0,0 +1,128 @@
+use crate::qubits::QuReg;
+use crate::QReal;
+
+/// One of the fixed gates wrapped by `QuReg`, with its qubits and angle, so
+/// that it can be inverted.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{Gate, QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(1, &env);
+/// let gates = [Gate::Hadamard(0), Gate::T(0), Gate::RotateX(0, 0.3)];
+///
+/// for gate in &gates {
+///     gate.apply(&mut qubits);
+/// }
+/// for gate in gates.iter().rev() {
+///     gate.inverse().apply(&mut qubits);
+/// }
+/// assert!((qubits.probability_of_state(0) - 1.0).abs() < 1e-10);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gate {
+    Hadamard(i32),
+    PauliX(i32),
+    PauliY(i32),
+    PauliZ(i32),
+    S(i32),
+    Sdg(i32),
+    T(i32),
+    Tdg(i32),
+    RotateX(i32, QReal),
+    RotateY(i32, QReal),
+    RotateZ(i32, QReal),
+    PhaseShift(i32, QReal),
+    /// A CNOT from the control qubit to the target qubit.
+    ControlledNot(i32, i32),
+    ControlledZ(i32, i32),
+    /// A phase shift of the target qubit, controlled on the control qubit.
+    ControlledPhaseShift(i32, i32, QReal),
+    Swap(i32, i32),
+    /// A doubly controlled NOT, with the target qubit last.
+    Toffoli(i32, i32, i32),
+}
+
+impl Gate {
+    /// The gate that undoes this one. Rotations and phase shifts are
+    /// negated, S and T become their adjoints, and the other gates are
+    /// their own inverses.
+    pub fn inverse(&self) -> Gate {
+        match *self {
+            Gate::S(qubit) => Gate::Sdg(qubit),
+            Gate::Sdg(qubit) => Gate::S(qubit),
+            Gate::T(qubit) => Gate::Tdg(qubit),
+            Gate::Tdg(qubit) => Gate::T(qubit),
+            Gate::RotateX(qubit, angle) => Gate::RotateX(qubit, -angle),
+            Gate::RotateY(qubit, angle) => Gate::RotateY(qubit, -angle),
+            Gate::RotateZ(qubit, angle) => Gate::RotateZ(qubit, -angle),
+            Gate::PhaseShift(qubit, angle) => Gate::PhaseShift(qubit, -angle),
+            Gate::ControlledPhaseShift(control, target, angle) => {
+                Gate::ControlledPhaseShift(control, target, -angle)
+            }
+            Gate::Hadamard(_)
+            | Gate::PauliX(_)
+            | Gate::PauliY(_)
+            | Gate::PauliZ(_)
+            | Gate::ControlledNot(..)
+            | Gate::ControlledZ(..)
+            | Gate::Swap(..)
+            | Gate::Toffoli(..) => *self,
+        }
+    }
+
+    pub fn apply<'q, 'a>(&self, qureg: &'q mut QuReg<'a>) -> &'q mut QuReg<'a> {
+        match *self {
+            Gate::Hadamard(qubit) => qureg.hadamard(qubit),
+            Gate::PauliX(qubit) => qureg.pauli_x(qubit),
+            Gate::PauliY(qubit) => qureg.pauli_y(qubit),
+            Gate::PauliZ(qubit) => qureg.pauli_z(qubit),
+            Gate::S(qubit) => qureg.apply_s_gate(qubit),
+            Gate::Sdg(qubit) => qureg.sdg(qubit),
+            Gate::T(qubit) => qureg.apply_t_gate(qubit),
+            Gate::Tdg(qubit) => qureg.tdg(qubit),
+            Gate::RotateX(qubit, angle) => qureg.rotate_x(qubit, angle),
+            Gate::RotateY(qubit, angle) => qureg.rotate_y(qubit, angle),
+            Gate::RotateZ(qubit, angle) => qureg.rotate_z(qubit, angle),
+            Gate::PhaseShift(qubit, angle) => qureg.phase_shift(qubit, angle),
+            Gate::ControlledNot(control, target) => qureg.controlled_not(control, target),
+            Gate::ControlledZ(qubit_one, qubit_two) => qureg.controlled_z(qubit_one, qubit_two),
+            Gate::ControlledPhaseShift(control, target, angle) => {
+                qureg.controlled_phase_shift(control, target, angle)
+            }
+            Gate::Swap(qubit_one, qubit_two) => qureg.swap_gate(qubit_one, qubit_two),
+            Gate::Toffoli(control_one, control_two, target) => {
+                qureg.toffoli(control_one, control_two, target)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gate;
+    use crate::{assert_qureg_close, QuReg, QuestEnv};
+
+    #[test]
+    fn gates_followed_by_their_inverses_are_identity() {
+        let env = QuestEnv::new();
+        let gates = [
+            Gate::S(0),
+            Gate::T(1),
+            Gate::Sdg(2),
+            Gate::RotateY(0, 0.4),
+            Gate::ControlledPhaseShift(1, 2, 0.9),
+            Gate::Toffoli(0, 1, 2),
+        ];
+
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state().rotate_x(1, 0.3);
+        let initial = qubits.clone();
+        for gate in &gates {
+            gate.apply(&mut qubits);
+            gate.inverse().apply(&mut qubits);
+            assert_qureg_close(&qubits, &initial, 1e-10);
+        }
+    }
+}
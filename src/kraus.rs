@@ -0,0 +1,152 @@
+use crate::{Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, QReal};
+
+/// Check that a set of single-qubit Kraus operators forms a trace-preserving
+/// channel, i.e. that the sum of `K†K` over all operators is the identity to
+/// within `tolerance`.
+///
+/// QuEST aborts if `mix_kraus_map` is given a map that isn't trace
+/// preserving, so this lets the map be checked up front.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::kraus_is_trace_preserving;
+/// use quest_rs::ComplexMatrix2;
+///
+/// let gamma: f64 = 0.3;
+/// let amplitude_damping = [
+///     ComplexMatrix2::real([[1.0, 0.0], [0.0, (1.0 - gamma).sqrt()]]),
+///     ComplexMatrix2::real([[0.0, gamma.sqrt()], [0.0, 0.0]]),
+/// ];
+/// assert!(kraus_is_trace_preserving(&amplitude_damping, 1e-10));
+/// assert!(!kraus_is_trace_preserving(&amplitude_damping[..1], 1e-10));
+/// ```
+pub fn kraus_is_trace_preserving(operators: &[ComplexMatrix2], tolerance: QReal) -> bool {
+    sums_to_identity(2, operators.len(), tolerance, |k, i, j| {
+        Complex::new(operators[k].real[i][j], operators[k].imag[i][j])
+    })
+}
+
+/// Two-qubit equivalent of `kraus_is_trace_preserving`, for the operators
+/// given to `mix_two_qubit_kraus_map`.
+pub fn two_qubit_kraus_is_trace_preserving(operators: &[ComplexMatrix4], tolerance: QReal) -> bool {
+    sums_to_identity(4, operators.len(), tolerance, |k, i, j| {
+        Complex::new(operators[k].real[i][j], operators[k].imag[i][j])
+    })
+}
+
+/// Multi-qubit equivalent of `kraus_is_trace_preserving`, for the operators
+/// given to `mix_multi_qubit_kraus_map`. Operators of differing sizes are
+/// never trace preserving.
+pub fn multi_qubit_kraus_is_trace_preserving(
+    operators: &[ComplexMatrixN],
+    tolerance: QReal,
+) -> bool {
+    let dim = match operators.first() {
+        Some(operator) => operator.num_rows,
+        None => return false,
+    };
+    if operators.iter().any(|operator| operator.num_rows != dim) {
+        return false;
+    }
+
+    sums_to_identity(dim, operators.len(), tolerance, |k, i, j| {
+        operators[k].get(i, j)
+    })
+}
+
+fn sums_to_identity<F>(dim: usize, num_operators: usize, tolerance: QReal, entry: F) -> bool
+where
+    F: Fn(usize, usize, usize) -> Complex,
+{
+    for i in 0..dim {
+        for j in 0..dim {
+            // (K†K)_ij = sum_r conj(K_ri) K_rj
+            let mut sum = Complex::zero();
+            for k in 0..num_operators {
+                for r in 0..dim {
+                    let (a, b) = (entry(k, r, i), entry(k, r, j));
+                    sum.real += a.real * b.real + a.imag * b.imag;
+                    sum.imag += a.real * b.imag - a.imag * b.real;
+                }
+            }
+
+            let expected = if i == j { 1.0 } else { 0.0 };
+            if (sum.real - expected).abs() > tolerance || sum.imag.abs() > tolerance {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        kraus_is_trace_preserving, multi_qubit_kraus_is_trace_preserving,
+        two_qubit_kraus_is_trace_preserving,
+    };
+    use crate::{ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, QReal};
+
+    fn depolarising(probability: QReal) -> Vec<ComplexMatrix2> {
+        let identity = (1.0 - probability).sqrt();
+        let pauli = (probability / 3.0).sqrt();
+        vec![
+            ComplexMatrix2::real([[identity, 0.0], [0.0, identity]]),
+            ComplexMatrix2::real([[0.0, pauli], [pauli, 0.0]]),
+            ComplexMatrix2::imag([[0.0, -pauli], [pauli, 0.0]]),
+            ComplexMatrix2::real([[pauli, 0.0], [0.0, -pauli]]),
+        ]
+    }
+
+    fn amplitude_damping(gamma: QReal) -> Vec<ComplexMatrix2> {
+        vec![
+            ComplexMatrix2::real([[1.0, 0.0], [0.0, (1.0 - gamma).sqrt()]]),
+            ComplexMatrix2::real([[0.0, gamma.sqrt()], [0.0, 0.0]]),
+        ]
+    }
+
+    #[test]
+    fn standard_channels_are_trace_preserving() {
+        assert!(kraus_is_trace_preserving(&depolarising(0.2), 1e-10));
+        assert!(kraus_is_trace_preserving(&amplitude_damping(0.4), 1e-10));
+    }
+
+    #[test]
+    fn truncated_channels_are_not_trace_preserving() {
+        assert!(!kraus_is_trace_preserving(&depolarising(0.2)[..3], 1e-10));
+        assert!(!kraus_is_trace_preserving(
+            &amplitude_damping(0.4)[1..],
+            1e-10
+        ));
+        assert!(!kraus_is_trace_preserving(&[], 1e-10));
+    }
+
+    #[test]
+    fn multi_qubit_channels() {
+        let mut swap = [[0.0; 4]; 4];
+        swap[0][0] = 1.0;
+        swap[1][2] = 1.0;
+        swap[2][1] = 1.0;
+        swap[3][3] = 1.0;
+        assert!(two_qubit_kraus_is_trace_preserving(
+            &[ComplexMatrix4::real(swap)],
+            1e-10
+        ));
+        assert!(!two_qubit_kraus_is_trace_preserving(
+            &[ComplexMatrix4::real([[0.5; 4]; 4])],
+            1e-10
+        ));
+
+        let mut identity = ComplexMatrixN::new(2);
+        let mut half_identity = ComplexMatrixN::new(2);
+        for i in 0..4 {
+            identity.set_real(i, i, 1.0);
+            half_identity.set_real(i, i, (0.5 as QReal).sqrt());
+        }
+        assert!(multi_qubit_kraus_is_trace_preserving(&[identity], 1e-10));
+        assert!(!multi_qubit_kraus_is_trace_preserving(
+            &[half_identity],
+            1e-10
+        ));
+    }
+}
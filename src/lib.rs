@@ -27,13 +27,13 @@
 //! let mut qubits = QuReg::new(2, &env);
 //! qubits.init_plus_state().hadamard(0).controlled_not(0, 1);
 //! println!(
-//!     "Probability amplitude of |11> *before* measurement is: {}",
-//!     qubits.probability_amplitude(0b11)
+//!     "Probability of |11> *before* measurement is: {}",
+//!     qubits.probability_of_state(0b11)
 //! );
 //! qubits.measure(1);
 //! println!(
-//!     "Probability amplitude of |11> *after* measurement is: {}",
-//!     qubits.probability_amplitude(0b11)
+//!     "Probability of |11> *after* measurement is: {}",
+//!     qubits.probability_of_state(0b11)
 //! );
 //! ```
 //!
@@ -83,7 +83,7 @@
 //!
 //! println!("Circuit output:");
 //! println!("---------------");
-//! println!("Probability amplitude of |111> is: {}", qubits.probability_amplitude(0b111));
+//! println!("Probability of |111> is: {}", qubits.probability_of_state(0b111));
 //! println!(
 //!     "Probability of qubit 2 being in state 1: {}",
 //!     qubits.calculate_probability_of_outcome(2, 1)
@@ -113,11 +113,41 @@
 //! The documentation should also be expanded to include all the relevant info
 //! from the QuEST documentation.
 
+use rand::{Rng, RngCore};
+
+pub mod algorithms;
+pub mod classical;
+pub mod controlled;
 pub mod environment;
+pub mod error;
+pub mod gate;
+pub mod kraus;
+mod linalg;
+pub mod pauli;
+pub mod pool;
+pub mod profile;
 pub mod qubits;
 
-pub use environment::{seed_quest, seed_quest_default, QuestEnv};
-pub use qubits::QuReg;
+pub use algorithms::{
+    amplitude_amplify, grover_search, parameter_shift_gradient, phase_estimation, select,
+    swap_test, teleport,
+};
+pub use classical::ClassicalRegister;
+pub use controlled::ControlledQuReg;
+pub use environment::{seed_quest, seed_quest_default, Capabilities, QuestEnv};
+pub use error::QuestError;
+pub use gate::Gate;
+pub use kraus::{
+    kraus_is_trace_preserving, multi_qubit_kraus_is_trace_preserving,
+    two_qubit_kraus_is_trace_preserving,
+};
+pub use pauli::{PauliString, PauliSum};
+pub use pool::{PooledQureg, QuregPool};
+pub use profile::{GateTiming, ProfileReport};
+pub use qubits::{
+    assert_qureg_close, basis_index_to_bits, bits_to_basis_index, estimate_state_vector_bytes,
+    memory_report, overlap_matrix, qureg_approx_eq, MemoryReport, QuReg,
+};
 
 // There's currently an issue with the 128-bit integer FFI due to upstream bugs in LLVM.
 // This isn't a problem when we're in single or double precision but there are hundreds
@@ -140,6 +170,48 @@ mod ffi {
 // features maybe?
 pub type QReal = f64; // QuEST also supports f32 and f128.
 
+/// A tolerance for comparing results that suits the precision of `QReal`:
+/// loose enough to absorb the rounding error of a typical circuit, but well
+/// above the machine epsilon. Used by the `is_unitary` checks, and a sensible
+/// choice for `qureg_approx_eq` and `assert_qureg_close`.
+pub const DEFAULT_TOLERANCE: QReal = match std::mem::size_of::<QReal>() {
+    4 => 1e-5,
+    8 => 1e-10,
+    _ => 1e-12,
+};
+
+// The decimal places QuEST's `REAL_STRING_FORMAT` prints for each precision.
+const REAL_DECIMALS: usize = match std::mem::size_of::<QReal>() {
+    4 => 8,
+    8 => 14,
+    _ => 17,
+};
+
+/// Format `x` with as many decimal places as QuEST's own `REAL_STRING_FORMAT`
+/// uses for the compiled precision: 8 in single, 14 in double and 17 in quad
+/// precision.
+pub fn format_real(x: QReal) -> String {
+    format!("{:.*}", REAL_DECIMALS, x)
+}
+
+// Whether the `dim`-by-`dim` matrix with elements `get(i, j)` satisfies
+// `U U^† = I` to within `DEFAULT_TOLERANCE`.
+fn is_unitary(dim: usize, get: impl Fn(usize, usize) -> Complex) -> bool {
+    (0..dim).all(|i| {
+        (0..dim).all(|j| {
+            let (real, imag) = (0..dim).fold((0.0, 0.0), |(real, imag), k| {
+                let (a, b) = (get(i, k), get(j, k));
+                (
+                    real + a.real * b.real + a.imag * b.imag,
+                    imag + a.imag * b.real - a.real * b.imag,
+                )
+            });
+            let expected = if i == j { 1.0 } else { 0.0 };
+            (real - expected).abs() <= DEFAULT_TOLERANCE && imag.abs() <= DEFAULT_TOLERANCE
+        })
+    })
+}
+
 // TODO: Figure out same thing but for OpenMP + MPI + GPU.
 
 // TODO: `syncQuESTSuccess(int successCode)` also exists but it looks more like
@@ -315,6 +387,52 @@ impl ComplexMatrix2 {
             imag,
         }
     }
+
+    /// A Haar-random unitary, drawn from `rng`.
+    ///
+    /// A uniformly random unit quaternion gives a random rotation in SU(2),
+    /// which is then multiplied by a uniformly random global phase.
+    pub fn random(rng: &mut impl RngCore) -> Self {
+        let (first, second) = (linalg::complex_gaussian(rng), linalg::complex_gaussian(rng));
+        let norm = (first.real * first.real
+            + first.imag * first.imag
+            + second.real * second.real
+            + second.imag * second.imag)
+            .sqrt();
+        let (a, b) = (
+            Complex::new(first.real / norm, first.imag / norm),
+            Complex::new(second.real / norm, second.imag / norm),
+        );
+        let phase = 2.0 * std::f64::consts::PI * rng.gen::<QReal>();
+        let (cos, sin) = (phase.cos(), phase.sin());
+        let rotate =
+            |z: Complex| Complex::new(z.real * cos - z.imag * sin, z.real * sin + z.imag * cos);
+
+        ComplexMatrix2::compact([
+            [rotate(a), rotate(b)],
+            [
+                rotate(Complex::new(-b.real, b.imag)),
+                rotate(Complex::new(a.real, -a.imag)),
+            ],
+        ])
+    }
+
+    /// The conjugate transpose of the matrix.
+    pub fn adjoint(&self) -> Self {
+        let mut adjoint = ComplexMatrix2::real([[0.0; 2]; 2]);
+        for i in 0..2 {
+            for j in 0..2 {
+                adjoint.real[i][j] = self.real[j][i];
+                adjoint.imag[i][j] = -self.imag[j][i];
+            }
+        }
+        adjoint
+    }
+
+    /// Whether the matrix is unitary to within `DEFAULT_TOLERANCE`.
+    pub fn is_unitary(&self) -> bool {
+        is_unitary(2, |i, j| Complex::new(self.real[i][j], self.imag[i][j]))
+    }
 }
 
 impl From<ComplexMatrix2> for ffi::ComplexMatrix2 {
@@ -441,6 +559,11 @@ impl ComplexMatrix4 {
             imag,
         }
     }
+
+    /// Whether the matrix is unitary to within `DEFAULT_TOLERANCE`.
+    pub fn is_unitary(&self) -> bool {
+        is_unitary(4, |i, j| Complex::new(self.real[i][j], self.imag[i][j]))
+    }
 }
 
 impl From<ComplexMatrix4> for ffi::ComplexMatrix4 {
@@ -513,13 +636,39 @@ impl ComplexMatrixN {
         self
     }
 
+    /// The number of rows (and columns) of the matrix, `2^num_qubits`.
+    pub fn dim(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Whether the matrix is unitary to within `DEFAULT_TOLERANCE`.
+    pub fn is_unitary(&self) -> bool {
+        is_unitary(self.num_rows, |i, j| self.get(i, j))
+    }
+
+    /// A Haar-random unitary on `num_qubits` qubits, drawn from `rng`.
+    pub fn random(num_qubits: i32, rng: &mut impl RngCore) -> Self {
+        let mut matrix = ComplexMatrixN::new(num_qubits);
+        let unitary = linalg::random_unitary(matrix.dim(), rng);
+        for (i, row) in unitary.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                matrix.set_real(i, j, value.real).set_imag(i, j, value.imag);
+            }
+        }
+        matrix
+    }
+
     pub fn display(&self) -> String {
         let mut out = String::new();
         for i in 0..self.num_rows {
             out.push_str("[");
             for j in 0..self.num_rows {
                 let value = self.get(i, j);
-                out.push_str(&format!("({:.5} + {:.5}j)", value.real, value.imag));
+                out.push_str(&format!(
+                    "({} + {}j)",
+                    format_real(value.real),
+                    format_real(value.imag)
+                ));
                 if j != self.num_rows - 1 {
                     out.push_str("\t");
                 }
@@ -590,7 +739,7 @@ impl From<ComplexMatrixN> for ffi::ComplexMatrixN {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PauliOpType {
     PauliI,
     PauliX,
@@ -609,9 +758,72 @@ impl From<PauliOpType> for ffi::pauliOpType {
     }
 }
 
+/// The result of measuring a qubit in the computational basis.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Zero,
+    One,
+}
+
+impl Outcome {
+    /// The outcome for the bit 0 or 1, as returned by `QuReg::measure`.
+    /// Panics for any other value.
+    pub fn from_bit(bit: i32) -> Self {
+        match bit {
+            0 => Outcome::Zero,
+            1 => Outcome::One,
+            _ => panic!("Measurement outcome {} is not 0 or 1.", bit),
+        }
+    }
+}
+
+impl From<Outcome> for i32 {
+    fn from(item: Outcome) -> Self {
+        match item {
+            Outcome::Zero => 0,
+            Outcome::One => 1,
+        }
+    }
+}
+
+/// The outcome of measuring a qubit, and the probability it had of being
+/// measured, as returned by `QuReg::measure_result`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeasurementResult {
+    pub outcome: i32,
+    pub probability: QReal,
+}
+
+/// Which of two qubits a 4x4 matrix treats as the most significant bit of
+/// its row and column indices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QubitOrdering {
+    /// The first qubit is the least significant bit, as in QuEST's own
+    /// `two_qubit_unitary` and in Qiskit.
+    LittleEndian,
+    /// The first qubit is the most significant bit, as in most textbooks.
+    BigEndian,
+}
+
+/// Which pairs of qubits are entangled with CNOTs in each layer of
+/// `QuReg::apply_hardware_efficient_ansatz`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntanglerPattern {
+    /// A CNOT from each qubit to the next, `(0, 1), (1, 2), ...`.
+    Linear,
+    /// A CNOT from each qubit to every later one.
+    AllToAll,
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Complex, ComplexMatrix2, ComplexMatrixN, QReal, QuReg, QuestEnv, Vector};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{
+        format_real, Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, QReal, QuReg,
+        QuestEnv, Vector, DEFAULT_TOLERANCE,
+    };
 
     #[test]
     fn two_qubit_circuit() {
@@ -620,18 +832,15 @@ mod tests {
         let mut qubits = QuReg::new(2, &env);
         qubits.init_plus_state().hadamard(0).controlled_not(0, 1);
 
-        let prob_amp_before = qubits.probability_amplitude(0b11);
+        let prob_before = qubits.probability_of_state(0b11);
         println!(
-            "Probability amplitude of |11> *before* measurement is: {}",
-            prob_amp_before
+            "Probability of |11> *before* measurement is: {}",
+            prob_before
         );
 
         qubits.measure(1);
-        let prob_amp_after = qubits.probability_amplitude(0b11);
-        println!(
-            "Probability amplitude of |11> *after* measurement is: {}",
-            prob_amp_after
-        );
+        let prob_after = qubits.probability_of_state(0b11);
+        println!("Probability of |11> *after* measurement is: {}", prob_after);
     }
 
     #[test]
@@ -681,9 +890,9 @@ mod tests {
         // Also compare against values taken manually from directly running
         // equivalent C code.
 
-        let prob_amp_state_111 = qubits.probability_amplitude(0b111);
-        println!("Probability amplitude of |111> is: {}", prob_amp_state_111);
-        // TODO: Assert that this probability amplitude == value from running native library code.
+        let prob_state_111 = qubits.probability_of_state(0b111);
+        println!("Probability of |111> is: {}", prob_state_111);
+        // TODO: Assert that this probability == value from running native library code.
 
         let prob_qubit_two_in_state_1 = qubits.calculate_probability_of_outcome(2, 1);
         println!(
@@ -699,4 +908,59 @@ mod tests {
             outcome, outcome_probability
         );
     }
+
+    #[test]
+    fn default_tolerance_scales_with_precision() {
+        let tolerance = DEFAULT_TOLERANCE;
+        assert!(tolerance > 1000.0 * QReal::EPSILON);
+        assert!(tolerance < QReal::EPSILON.sqrt());
+    }
+
+    #[test]
+    fn format_real_keeps_precision() {
+        let x: QReal = 1.0 / 3.0;
+        let formatted = format_real(x);
+        let decimals = formatted.split('.').nth(1).unwrap().len();
+        assert!(decimals >= QReal::DIGITS as usize - 1);
+
+        let parsed: QReal = formatted.parse().unwrap();
+        assert!((parsed - x).abs() <= (10.0 as QReal).powi(-(decimals as i32)));
+        assert_ne!(format_real(x), format_real(x + DEFAULT_TOLERANCE));
+    }
+
+    #[test]
+    fn unitarity_checks() {
+        let hadamard = (0.5 as QReal).sqrt();
+        assert!(ComplexMatrix2::real([[hadamard, hadamard], [hadamard, -hadamard]]).is_unitary());
+        assert!(
+            ComplexMatrix2::new([[0.0, 0.0], [0.0, 0.0]], [[0.0, -1.0], [1.0, 0.0]]).is_unitary()
+        );
+        assert!(!ComplexMatrix2::real([[1.0, 1.0], [0.0, 1.0]]).is_unitary());
+
+        let mut swap = [[0.0; 4]; 4];
+        swap[0][0] = 1.0;
+        swap[1][2] = 1.0;
+        swap[2][1] = 1.0;
+        swap[3][3] = 1.0;
+        assert!(ComplexMatrix4::real(swap).is_unitary());
+        swap[3][3] = 1.0 + 10.0 * DEFAULT_TOLERANCE;
+        assert!(!ComplexMatrix4::real(swap).is_unitary());
+
+        let mut phase = ComplexMatrixN::new(1);
+        phase.set_real(0, 0, 1.0).set_imag(1, 1, 1.0);
+        assert!(phase.is_unitary());
+        phase.set_real(0, 1, 0.5);
+        assert!(!phase.is_unitary());
+    }
+
+    #[test]
+    fn random_matrices_are_unitary() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..20 {
+            assert!(ComplexMatrix2::random(&mut rng).is_unitary());
+        }
+        for num_qubits in 1..4 {
+            assert!(ComplexMatrixN::random(num_qubits, &mut rng).is_unitary());
+        }
+    }
 }
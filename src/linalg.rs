@@ -0,0 +1,217 @@
+// Small dense linear-algebra helpers for the calculations QuEST doesn't
+// provide itself. These are only meant for the handful of qubits whose
+// matrices are practical to extract from a register.
+
+use std::f64::consts::PI;
+
+use rand::{Rng, RngCore};
+
+use crate::{Complex, QReal};
+
+/// Eigenvalues of a Hermitian matrix, in ascending order.
+///
+/// The `n x n` Hermitian matrix `A + iB` is embedded in the `2n x 2n` real
+/// symmetric matrix `[[A, -B], [B, A]]`, which has the same eigenvalues with
+/// each one repeated twice, and that is diagonalised with Jacobi rotations.
+pub(crate) fn hermitian_eigenvalues(matrix: &[Vec<Complex>]) -> Vec<QReal> {
+    let (mut eigenvalues, _) = symmetric_eigen(real_embedding(matrix));
+    eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    eigenvalues.into_iter().step_by(2).collect()
+}
+
+/// The Uhlmann fidelity `(Tr sqrt(sqrt(rho) sigma sqrt(rho)))^2` of two
+/// density matrices.
+///
+/// The real embedding preserves products and positive square roots, so the
+/// calculation is done on the embedded matrices, where each eigenvalue, and
+/// so the trace, is doubled.
+pub(crate) fn uhlmann_fidelity(rho: &[Vec<Complex>], sigma: &[Vec<Complex>]) -> QReal {
+    let sqrt_rho = symmetric_sqrt(real_embedding(rho));
+    let sigma = real_embedding(sigma);
+    let product = multiply(&multiply(&sqrt_rho, &sigma), &sqrt_rho);
+    let (eigenvalues, _) = symmetric_eigen(product);
+    let trace: QReal = eigenvalues
+        .into_iter()
+        .map(|eigenvalue| eigenvalue.max(0.0).sqrt())
+        .sum();
+    (trace / 2.0).powi(2)
+}
+
+fn real_embedding(matrix: &[Vec<Complex>]) -> Vec<Vec<QReal>> {
+    let dim = matrix.len();
+    let mut real = vec![vec![0.0; 2 * dim]; 2 * dim];
+    for i in 0..dim {
+        for j in 0..dim {
+            let value = matrix[i][j];
+            real[i][j] = value.real;
+            real[i + dim][j + dim] = value.real;
+            real[i][j + dim] = -value.imag;
+            real[i + dim][j] = value.imag;
+        }
+    }
+    real
+}
+
+// The positive square root of a positive semi-definite symmetric matrix,
+// clamping eigenvalues that rounding has made slightly negative.
+fn symmetric_sqrt(a: Vec<Vec<QReal>>) -> Vec<Vec<QReal>> {
+    let n = a.len();
+    let (eigenvalues, vectors) = symmetric_eigen(a);
+    let roots: Vec<QReal> = eigenvalues.iter().map(|e| e.max(0.0).sqrt()).collect();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    (0..n)
+                        .map(|k| vectors[i][k] * roots[k] * vectors[j][k])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn multiply(a: &[Vec<QReal>], b: &[Vec<QReal>]) -> Vec<Vec<QReal>> {
+    let n = a.len();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| (0..n).map(|k| a[i][k] * b[k][j]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+/// The eigenvalues of a real symmetric matrix, unsorted, and a matrix whose
+/// columns are the matching eigenvectors.
+fn symmetric_eigen(mut a: Vec<Vec<QReal>>) -> (Vec<QReal>, Vec<Vec<QReal>>) {
+    let n = a.len();
+    let mut vectors: Vec<Vec<QReal>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for _sweep in 0..100 {
+        let off_diagonal: QReal = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off_diagonal < 1e-24 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for row in a.iter_mut().chain(vectors.iter_mut()) {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (row_p, row_q) = (a[p].clone(), a[q].clone());
+                for (k, (apk, aqk)) in row_p.into_iter().zip(row_q).enumerate() {
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+            }
+        }
+    }
+
+    ((0..n).map(|i| a[i][i]).collect(), vectors)
+}
+
+/// A sample of the standard complex normal distribution, whose real and
+/// imaginary parts are independent normals with variance 1/2, drawn with the
+/// Box-Muller transform.
+pub(crate) fn complex_gaussian(rng: &mut impl RngCore) -> Complex {
+    // 1 - u is in (0, 1], so its logarithm is finite.
+    let radius = (-(1.0 - rng.gen::<QReal>()).ln()).sqrt();
+    let angle = 2.0 * PI * rng.gen::<QReal>();
+    Complex::new(radius * angle.cos(), radius * angle.sin())
+}
+
+/// A Haar-random `dim x dim` unitary, as rows of elements.
+///
+/// The columns of a matrix of complex Gaussians are orthonormalised with
+/// Gram-Schmidt, which is its QR decomposition with a positive real diagonal
+/// in `R`, so `Q` is distributed by the Haar measure.
+pub(crate) fn random_unitary(dim: usize, rng: &mut impl RngCore) -> Vec<Vec<Complex>> {
+    let mut columns: Vec<Vec<Complex>> = (0..dim)
+        .map(|_| (0..dim).map(|_| complex_gaussian(rng)).collect())
+        .collect();
+
+    for j in 0..dim {
+        let (orthonormal, rest) = columns.split_at_mut(j);
+        let column = &mut rest[0];
+        for q in orthonormal.iter() {
+            // Subtract the projection <q|v> q.
+            let (real, imag) =
+                q.iter()
+                    .zip(column.iter())
+                    .fold((0.0, 0.0), |(real, imag), (q, v)| {
+                        (
+                            real + q.real * v.real + q.imag * v.imag,
+                            imag + q.real * v.imag - q.imag * v.real,
+                        )
+                    });
+            for (q, v) in q.iter().zip(column.iter_mut()) {
+                v.real -= real * q.real - imag * q.imag;
+                v.imag -= real * q.imag + imag * q.real;
+            }
+        }
+
+        let norm = column
+            .iter()
+            .map(|v| v.real * v.real + v.imag * v.imag)
+            .sum::<QReal>()
+            .sqrt();
+        for v in column.iter_mut() {
+            v.real /= norm;
+            v.imag /= norm;
+        }
+    }
+
+    (0..dim)
+        .map(|i| (0..dim).map(|j| columns[j][i]).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hermitian_eigenvalues, uhlmann_fidelity};
+    use crate::Complex;
+
+    #[test]
+    fn eigenvalues_of_pauli_y() {
+        let pauli_y = vec![
+            vec![Complex::zero(), Complex::imag(-1.0)],
+            vec![Complex::imag(1.0), Complex::zero()],
+        ];
+        let eigenvalues = hermitian_eigenvalues(&pauli_y);
+        assert!((eigenvalues[0] + 1.0).abs() < 1e-10);
+        assert!((eigenvalues[1] - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn fidelity_of_commuting_states_matches_classical_fidelity() {
+        let diagonal = |probabilities: &[f64]| -> Vec<Vec<Complex>> {
+            (0..probabilities.len())
+                .map(|i| {
+                    (0..probabilities.len())
+                        .map(|j| Complex::real(if i == j { probabilities[i] } else { 0.0 }))
+                        .collect()
+                })
+                .collect()
+        };
+        let rho = diagonal(&[0.5, 0.5]);
+        let sigma = diagonal(&[0.9, 0.1]);
+        let expected = ((0.5f64 * 0.9).sqrt() + (0.5f64 * 0.1).sqrt()).powi(2);
+        assert!((uhlmann_fidelity(&rho, &sigma) - expected).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,367 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+use crate::{PauliOpType, QReal};
+
+/// A weighted tensor product of Pauli operators, one for each qubit.
+///
+/// Strings are written with qubit 0 on the left, so `"XIZ"` is X on qubit 0,
+/// the identity on qubit 1 and Z on qubit 2. This is the same order that
+/// `calculate_expected_pauli_sum` expects its Pauli codes in.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{PauliString, QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(2, &env);
+/// qubits.init_zero_state().hadamard(0);
+///
+/// let x0 = PauliString::parse("XI").unwrap();
+/// let (targets, paulis) = x0.to_pauli_product();
+/// let expectation =
+///     qubits.calculate_expected_pauli_product(targets, paulis, QuReg::new(2, &env));
+/// assert!((x0.coefficient() * expectation - 1.0).abs() < 1e-10);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliString {
+    paulis: Vec<PauliOpType>,
+    coefficient: QReal,
+}
+
+impl PauliString {
+    pub fn new(paulis: Vec<PauliOpType>, coefficient: QReal) -> Self {
+        PauliString {
+            paulis,
+            coefficient,
+        }
+    }
+
+    /// Parse a string of `I`, `X`, `Y` and `Z` characters, with a coefficient
+    /// of 1.
+    pub fn parse(pauli_string: &str) -> Result<Self, ParseError> {
+        if pauli_string.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        let paulis = pauli_string
+            .chars()
+            .enumerate()
+            .map(|(position, character)| match character {
+                'I' => Ok(PauliOpType::PauliI),
+                'X' => Ok(PauliOpType::PauliX),
+                'Y' => Ok(PauliOpType::PauliY),
+                'Z' => Ok(PauliOpType::PauliZ),
+                _ => Err(ParseError::InvalidOperator {
+                    position,
+                    character,
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self::new(paulis, 1.0))
+    }
+
+    /// The same operators with a different coefficient.
+    pub fn with_coefficient(self, coefficient: QReal) -> Self {
+        PauliString {
+            coefficient,
+            ..self
+        }
+    }
+
+    pub fn paulis(&self) -> &[PauliOpType] {
+        &self.paulis
+    }
+
+    pub fn coefficient(&self) -> QReal {
+        self.coefficient
+    }
+
+    pub fn num_qubits(&self) -> i32 {
+        self.paulis.len() as i32
+    }
+
+    /// The target qubits and operators to pass to
+    /// `calculate_expected_pauli_product`. The coefficient is not included.
+    pub fn to_pauli_product(&self) -> (Vec<i32>, Vec<PauliOpType>) {
+        ((0..self.num_qubits()).collect(), self.paulis.clone())
+    }
+}
+
+impl FromStr for PauliString {
+    type Err = ParseError;
+
+    fn from_str(pauli_string: &str) -> Result<Self, Self::Err> {
+        Self::parse(pauli_string)
+    }
+}
+
+impl fmt::Display for PauliString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.coefficient)?;
+        for pauli in &self.paulis {
+            let character = match pauli {
+                PauliOpType::PauliI => 'I',
+                PauliOpType::PauliX => 'X',
+                PauliOpType::PauliY => 'Y',
+                PauliOpType::PauliZ => 'Z',
+            };
+            write!(f, "{}", character)?;
+        }
+        Ok(())
+    }
+}
+
+impl Mul<QReal> for PauliString {
+    type Output = PauliString;
+
+    fn mul(self, factor: QReal) -> Self::Output {
+        let coefficient = self.coefficient * factor;
+        self.with_coefficient(coefficient)
+    }
+}
+
+impl Mul<PauliString> for QReal {
+    type Output = PauliString;
+
+    fn mul(self, pauli_string: PauliString) -> Self::Output {
+        pauli_string * self
+    }
+}
+
+impl Add for PauliString {
+    type Output = PauliSum;
+
+    fn add(self, other: PauliString) -> Self::Output {
+        PauliSum::from(self) + other
+    }
+}
+
+/// A weighted sum of Pauli strings, such as a Hamiltonian.
+///
+/// Terms are kept in the order they were added, and like terms aren't
+/// combined. Terms on fewer qubits than the sum act as the identity on the
+/// remaining, higher qubits.
+///
+/// QuEST 3.1 has no `PauliHamil` type, so sums are converted into the flat
+/// code and coefficient arrays taken by `calculate_expected_pauli_sum` and
+/// `apply_pauli_sum` instead.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{PauliString, PauliSum, QuestEnv, QuReg};
+///
+/// let z0 = PauliString::parse("ZI").unwrap();
+/// let z1 = PauliString::parse("IZ").unwrap();
+/// let hamiltonian: PauliSum = 0.5 * z0 + 0.5 * z1;
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new(2, &env);
+/// qubits.init_zero_state();
+/// let (codes, coefficients) = hamiltonian.to_pauli_codes(2);
+/// let energy = qubits.calculate_expected_pauli_sum(codes, coefficients, QuReg::new(2, &env));
+/// assert!((energy - 1.0).abs() < 1e-10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PauliSum {
+    terms: Vec<PauliString>,
+}
+
+impl PauliSum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn terms(&self) -> &[PauliString] {
+        &self.terms
+    }
+
+    /// The number of qubits of the longest term.
+    pub fn num_qubits(&self) -> i32 {
+        self.terms
+            .iter()
+            .map(PauliString::num_qubits)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The Pauli codes of every term on `num_qubits` qubits, concatenated,
+    /// and the coefficient of each term.
+    ///
+    /// Panics if any term acts on more than `num_qubits` qubits.
+    pub fn to_pauli_codes(&self, num_qubits: i32) -> (Vec<PauliOpType>, Vec<QReal>) {
+        if self.num_qubits() > num_qubits {
+            panic!(
+                "Pauli sum acts on {} qubits, which doesn't fit in {}.",
+                self.num_qubits(),
+                num_qubits
+            );
+        }
+
+        let mut codes = Vec::with_capacity(self.terms.len() * num_qubits as usize);
+        for term in &self.terms {
+            codes.extend_from_slice(term.paulis());
+            codes.extend((term.num_qubits()..num_qubits).map(|_| PauliOpType::PauliI));
+        }
+        let coefficients = self.terms.iter().map(PauliString::coefficient).collect();
+        (codes, coefficients)
+    }
+}
+
+impl From<PauliString> for PauliSum {
+    fn from(term: PauliString) -> Self {
+        PauliSum { terms: vec![term] }
+    }
+}
+
+impl Add<PauliString> for PauliSum {
+    type Output = PauliSum;
+
+    fn add(mut self, term: PauliString) -> Self::Output {
+        self.terms.push(term);
+        self
+    }
+}
+
+impl Add for PauliSum {
+    type Output = PauliSum;
+
+    fn add(mut self, other: PauliSum) -> Self::Output {
+        self.terms.extend(other.terms);
+        self
+    }
+}
+
+impl Mul<QReal> for PauliSum {
+    type Output = PauliSum;
+
+    fn mul(self, factor: QReal) -> Self::Output {
+        PauliSum {
+            terms: self.terms.into_iter().map(|term| term * factor).collect(),
+        }
+    }
+}
+
+impl Mul<PauliSum> for QReal {
+    type Output = PauliSum;
+
+    fn mul(self, pauli_sum: PauliSum) -> Self::Output {
+        pauli_sum * self
+    }
+}
+
+/// Why a string couldn't be parsed as a `PauliString`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The string has no operators.
+    Empty,
+    /// A character isn't one of `I`, `X`, `Y` or `Z`.
+    InvalidOperator { position: usize, character: char },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "Pauli string is empty"),
+            ParseError::InvalidOperator {
+                position,
+                character,
+            } => write!(
+                f,
+                "'{}' at position {} is not one of the Pauli operators I, X, Y or Z",
+                character, position
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseError, PauliString, PauliSum};
+    use crate::{PauliOpType, QReal, QuReg, QuestEnv};
+
+    #[test]
+    fn parse_pauli_string() {
+        let pauli_string = PauliString::parse("XYZ").unwrap();
+        assert_eq!(
+            pauli_string.paulis(),
+            &[
+                PauliOpType::PauliX,
+                PauliOpType::PauliY,
+                PauliOpType::PauliZ
+            ]
+        );
+        assert_eq!(pauli_string.coefficient(), 1.0);
+
+        let (targets, paulis) = pauli_string.with_coefficient(0.5).to_pauli_product();
+        assert_eq!(targets, vec![0, 1, 2]);
+        assert_eq!(paulis.len(), 3);
+
+        let parsed: PauliString = "IZ".parse().unwrap();
+        assert_eq!(parsed.to_string(), "1 IZ");
+    }
+
+    #[test]
+    fn parse_rejects_invalid_operators() {
+        assert_eq!(
+            PauliString::parse("XQZ"),
+            Err(ParseError::InvalidOperator {
+                position: 1,
+                character: 'Q'
+            })
+        );
+        assert_eq!(PauliString::parse(""), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn pauli_sum_arithmetic() {
+        let x0 = PauliString::parse("X").unwrap();
+        let z1 = PauliString::parse("IZ").unwrap();
+        let sum = 0.5 * x0 + z1 * 2.0;
+        let doubled: PauliSum = 2.0 * (sum.clone() + PauliSum::new());
+
+        let (codes, coefficients) = doubled.to_pauli_codes(3);
+        assert_eq!(coefficients, vec![1.0, 4.0]);
+        assert_eq!(
+            codes,
+            vec![
+                PauliOpType::PauliX,
+                PauliOpType::PauliI,
+                PauliOpType::PauliI,
+                PauliOpType::PauliI,
+                PauliOpType::PauliZ,
+                PauliOpType::PauliI
+            ]
+        );
+        assert_eq!(sum.num_qubits(), 2);
+    }
+
+    #[test]
+    fn pauli_sum_expectation_matches_terms() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_zero_state().rotate_y(0, 0.7).rotate_x(1, 0.3);
+
+        let hamiltonian = 0.3 * PauliString::parse("XY").unwrap()
+            + -1.2 * PauliString::parse("ZZ").unwrap()
+            + PauliString::parse("IZ").unwrap();
+
+        let (codes, coefficients) = hamiltonian.to_pauli_codes(2);
+        let energy = qubits.calculate_expected_pauli_sum(codes, coefficients, QuReg::new(2, &env));
+        let term_sum: QReal = hamiltonian
+            .terms()
+            .iter()
+            .map(|term| {
+                let (targets, paulis) = term.to_pauli_product();
+                term.coefficient()
+                    * qubits.calculate_expected_pauli_product(targets, paulis, QuReg::new(2, &env))
+            })
+            .sum();
+        assert!((energy - term_sum).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,155 @@
+use std::cell::{Cell, RefCell};
+use std::ops::{Deref, DerefMut};
+
+use crate::environment::QuestEnv;
+use crate::qubits::QuReg;
+
+/// A cache of equally-sized registers, so that tight loops (e.g. evaluating
+/// a variational circuit many times) don't have to allocate and free a new
+/// register on every iteration.
+///
+/// Registers are handed out as `PooledQureg` guards, which reset the register
+/// to the zero state on acquire and give it back to the pool when dropped.
+/// The guards borrow the pool, which in turn borrows the environment, so the
+/// compiler ensures everything is dropped in a safe order.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{QuestEnv, QuregPool};
+///
+/// let env = QuestEnv::new();
+/// let pool = QuregPool::new(3, &env);
+///
+/// for _ in 0..10 {
+///     let mut qubits = pool.acquire();
+///     qubits.hadamard(0).controlled_not(0, 1);
+/// }
+/// assert_eq!(pool.num_allocated(), 1);
+/// ```
+pub struct QuregPool<'a> {
+    env: &'a QuestEnv,
+    num_qubits: i32,
+    is_density: bool,
+    available: RefCell<Vec<QuReg<'a>>>,
+    num_allocated: Cell<usize>,
+}
+
+impl<'a> QuregPool<'a> {
+    /// Create an empty pool of state-vector registers with `num_qubits`
+    /// qubits each.
+    pub fn new(num_qubits: i32, env: &'a QuestEnv) -> Self {
+        QuregPool {
+            env,
+            num_qubits,
+            is_density: false,
+            available: RefCell::new(Vec::new()),
+            num_allocated: Cell::new(0),
+        }
+    }
+
+    /// Create an empty pool of density-matrix registers with `num_qubits`
+    /// qubits each.
+    pub fn new_density(num_qubits: i32, env: &'a QuestEnv) -> Self {
+        QuregPool {
+            is_density: true,
+            ..Self::new(num_qubits, env)
+        }
+    }
+
+    /// Take a register from the pool, allocating a new one only if none are
+    /// free, and initialise it to the zero state.
+    pub fn acquire(&self) -> PooledQureg<'_, 'a> {
+        let cached = self.available.borrow_mut().pop();
+        let mut qureg = cached.unwrap_or_else(|| {
+            self.num_allocated.set(self.num_allocated.get() + 1);
+            if self.is_density {
+                QuReg::new_density(self.num_qubits, self.env)
+            } else {
+                QuReg::new(self.num_qubits, self.env)
+            }
+        });
+        qureg.init_zero_state();
+
+        PooledQureg {
+            pool: self,
+            qureg: Some(qureg),
+        }
+    }
+
+    /// How many registers this pool has allocated in total.
+    pub fn num_allocated(&self) -> usize {
+        self.num_allocated.get()
+    }
+
+    /// How many allocated registers are currently free to be acquired.
+    pub fn num_available(&self) -> usize {
+        self.available.borrow().len()
+    }
+}
+
+/// A register borrowed from a `QuregPool`, returned to it on drop.
+pub struct PooledQureg<'p, 'a> {
+    pool: &'p QuregPool<'a>,
+    qureg: Option<QuReg<'a>>,
+}
+
+impl<'a> Deref for PooledQureg<'_, 'a> {
+    type Target = QuReg<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.qureg.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for PooledQureg<'_, 'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.qureg.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledQureg<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(qureg) = self.qureg.take() {
+            self.pool.available.borrow_mut().push(qureg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuregPool;
+    use crate::QuestEnv;
+
+    #[test]
+    fn pool_reuses_registers() {
+        let env = QuestEnv::new();
+        let pool = QuregPool::new(2, &env);
+
+        for _ in 0..100 {
+            let mut qubits = pool.acquire();
+            assert_eq!(qubits.probability_of_state(0), 1.0);
+            qubits.pauli_x(0).pauli_x(1);
+        }
+
+        assert_eq!(pool.num_allocated(), 1);
+        assert_eq!(pool.num_available(), 1);
+    }
+
+    #[test]
+    fn pool_allocates_when_all_are_in_use() {
+        let env = QuestEnv::new();
+        let pool = QuregPool::new_density(1, &env);
+
+        {
+            let first = pool.acquire();
+            let second = pool.acquire();
+            assert!(first.is_density_matrix() && second.is_density_matrix());
+            assert_eq!(pool.num_allocated(), 2);
+            assert_eq!(pool.num_available(), 0);
+        }
+
+        let _third = pool.acquire();
+        assert_eq!(pool.num_allocated(), 2);
+        assert_eq!(pool.num_available(), 1);
+    }
+}
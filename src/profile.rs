@@ -0,0 +1,79 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The calls made to one kind of gate, and the wall-clock time they took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GateTiming {
+    pub calls: usize,
+    pub total: Duration,
+}
+
+/// Time spent in each kind of gate applied to a register, recorded while
+/// profiling is enabled on its environment.
+///
+/// Gates are named after the `QuReg` method that applied them. Gates built
+/// from other gates, such as `apply_qft`, are attributed to the gates they
+/// are built from.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{QuestEnv, QuReg};
+///
+/// let mut env = QuestEnv::new();
+/// env.enable_profiling();
+///
+/// let mut qubits = QuReg::new(2, &env);
+/// qubits.hadamard(0).hadamard(1).controlled_not(0, 1);
+///
+/// let report = qubits.profile_report();
+/// assert_eq!(report.get("hadamard").unwrap().calls, 2);
+/// println!("{}", report);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileReport {
+    timings: BTreeMap<&'static str, GateTiming>,
+}
+
+impl ProfileReport {
+    /// The timing of `gate`, if it has been applied.
+    pub fn get(&self, gate: &str) -> Option<GateTiming> {
+        self.timings.get(gate).copied()
+    }
+
+    /// Every gate that has been applied, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, GateTiming)> + '_ {
+        self.timings.iter().map(|(gate, timing)| (*gate, *timing))
+    }
+
+    /// The time spent in all gates together.
+    pub fn total(&self) -> Duration {
+        self.timings.values().map(|timing| timing.total).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timings.is_empty()
+    }
+
+    pub(crate) fn time<T>(&mut self, gate: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        let timing = self.timings.entry(gate).or_default();
+        timing.calls += 1;
+        timing.total += start.elapsed();
+        result
+    }
+}
+
+// One line per gate, with the slowest first.
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut timings: Vec<_> = self.iter().collect();
+        timings.sort_by_key(|(_, timing)| Reverse(timing.total));
+        for (gate, timing) in timings {
+            writeln!(f, "{}: {} calls, {:?}", gate, timing.calls, timing.total)?;
+        }
+        Ok(())
+    }
+}
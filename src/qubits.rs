@@ -1,42 +1,140 @@
+use std::f64::consts::PI;
 use std::ffi::{CStr, CString};
+use std::mem::ManuallyDrop;
 use std::os::raw::c_char;
 
-use crate::environment::QuestEnv;
+use rand::{Rng, RngCore};
+
+use crate::classical::ClassicalRegister;
+use crate::controlled::ControlledQuReg;
+use crate::environment::{lock_rng, QuestEnv};
+use crate::error::QuestError;
 use crate::ffi;
-use crate::{Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, PauliOpType, QReal, Vector};
+use crate::linalg;
+use crate::profile::ProfileReport;
+use crate::{
+    format_real, Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN, EntanglerPattern,
+    MeasurementResult, Outcome, PauliOpType, QReal, QubitOrdering, Vector, DEFAULT_TOLERANCE,
+};
 
+/// A register of qubits, stored as a state vector or a density matrix.
+///
+/// Each `QuReg` uniquely owns the QuEST register behind it, which is
+/// destroyed exactly once when the `QuReg` is dropped. It is deliberately not
+/// `Copy`, and `clone` allocates a new register rather than sharing the
+/// handle.
+///
+/// Because each register owns independent memory, a `QuReg` is `Send` and can
+/// be moved to a worker thread. It isn't `Sync`, since QuEST's calculations
+/// use scratch space inside the register, even through `&QuReg`.
+///
+/// Initialisers, gates, `apply_*` operations and `mix_*` noise channels all
+/// return `&mut Self`, so a circuit can be written as one chain. Measurements
+/// and calculations return their result instead, so they end a chain.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{ComplexMatrix2, QReal, QuestEnv, QuReg};
+///
+/// let env = QuestEnv::new();
+/// let mut qubits = QuReg::new_density(3, &env);
+/// let pauli_x = ComplexMatrix2 {
+///     real: [[0.0, 1.0], [1.0, 0.0]],
+///     imag: [[0.0, 0.0], [0.0, 0.0]],
+/// };
+///
+/// qubits
+///     .init_zero_state()
+///     .hadamard(0)
+///     .unitary(1, pauli_x)
+///     .rotate_x(2, 0.3)
+///     .apply_diagonal_fn(|index| 0.1 * index as QReal)
+///     .apply_qft(&[0, 1, 2])
+///     .apply_inverse_qft(&[0, 1, 2])
+///     .mix_dephasing(0, 0.1)
+///     .mix_damping(2, 0.05);
+///
+/// let purity = qubits.calculate_purity();
+/// assert!(purity < 1.0);
+/// ```
 pub struct QuReg<'a> {
     env: &'a QuestEnv,
     reg: ffi::Qureg,
+    profile: ProfileReport,
 }
 
+// QuEST only shares its random number generator between registers, and that
+// is guarded by `lock_rng`. This only covers the CPU builds, though: in an MPI
+// build the communicator is only initialised for the main thread, and a GPU
+// build's device memory is tied to the thread that allocated it, so registers
+// of those environments must stay on the main thread, as `QuestEnv` says.
+// The backend is only known at run time, through `QuestEnv::capabilities`, so
+// this can't be enforced by the type.
+unsafe impl Send for QuReg<'_> {}
+
 impl<'a> QuReg<'a> {
     pub fn new(num_qubits: i32, env: &'a QuestEnv) -> Self {
         unsafe {
             QuReg {
                 reg: ffi::createQureg(num_qubits, env.into()),
                 env,
+                profile: ProfileReport::default(),
             }
         }
     }
 
+    /// Create a state-vector register, or return an error instead of letting
+    /// QuEST abort if `num_qubits` isn't positive, the register is too large
+    /// to allocate, or its estimated size exceeds the memory limit set on the
+    /// environment.
+    pub fn try_new(num_qubits: i32, env: &'a QuestEnv) -> Result<Self, QuestError> {
+        check_allocation(num_qubits, false, env)?;
+        Ok(Self::new(num_qubits, env))
+    }
+
+    /// Like `try_new`, but for a density-matrix register.
+    pub fn try_new_density(num_qubits: i32, env: &'a QuestEnv) -> Result<Self, QuestError> {
+        check_allocation(num_qubits, true, env)?;
+        Ok(Self::new_density(num_qubits, env))
+    }
+
     pub fn new_density(num_qubits: i32, env: &'a QuestEnv) -> Self {
         unsafe {
             QuReg {
                 reg: ffi::createDensityQureg(num_qubits, env.into()),
                 env,
+                profile: ProfileReport::default(),
             }
         }
     }
 
-    pub fn num_qubits(self) -> i32 {
+    pub fn num_qubits(&self) -> i32 {
         unsafe { ffi::getNumQubits(self.reg) }
     }
 
-    pub fn num_prob_amplitudes(self) -> i64 {
+    pub fn num_prob_amplitudes(&self) -> i64 {
         unsafe { ffi::getNumAmps(self.reg) }
     }
 
+    /// The number of amplitudes in the whole register, across all ranks of a
+    /// distributed environment. Density matrices store `4^num_qubits`.
+    ///
+    /// QuEST 3.1 has no `getNumAmpsTotal`, so this reads the register's own
+    /// record of its size.
+    pub fn num_amps_total(&self) -> i64 {
+        self.reg.numAmpsTotal
+    }
+
+    /// The number of amplitudes stored by this process, which is all of them
+    /// unless the environment is distributed.
+    pub fn num_amps_per_chunk(&self) -> i64 {
+        self.reg.numAmpsPerChunk
+    }
+
+    pub fn is_density_matrix(&self) -> bool {
+        self.reg.isDensityMatrix != 0
+    }
+
     // ---------------------
     // State Initialisations
     // ---------------------
@@ -82,6 +180,53 @@ impl<'a> QuReg<'a> {
         self
     }
 
+    /// Initialise a density matrix to the mixture `sum_i p_i |ψ_i><ψ_i|` of
+    /// the weighted state vectors in `components`.
+    ///
+    /// Panics unless the weights are nonnegative and sum to 1 to within
+    /// `DEFAULT_TOLERANCE`, and every component is a state vector with as many qubits as this register.
+    pub fn init_mixed_ensemble(&mut self, components: &[(QReal, &QuReg)]) -> &mut Self {
+        if !self.is_density_matrix() {
+            panic!("A mixed ensemble can only be stored in a density matrix.");
+        }
+        let num_qubits = self.reg.numQubitsRepresented;
+        for (weight, state) in components {
+            if state.is_density_matrix() || state.reg.numQubitsRepresented != num_qubits {
+                panic!(
+                    "Ensemble components must be state vectors of {} qubits.",
+                    num_qubits
+                );
+            }
+            if weight.is_nan() || *weight < 0.0 {
+                panic!("Ensemble weight {} isn't a valid probability.", weight);
+            }
+        }
+        let total: QReal = components.iter().map(|(weight, _)| weight).sum();
+        if (total - 1.0).abs() > DEFAULT_TOLERANCE {
+            panic!("Ensemble weights sum to {} rather than 1.", total);
+        }
+
+        let dim = 1i64 << num_qubits;
+        let amplitudes: Vec<(QReal, Vec<Complex>)> = components
+            .iter()
+            .map(|(weight, state)| (*weight, (0..dim).map(|i| state.amplitude(i)).collect()))
+            .collect();
+        self.set_local_amplitudes(|index| {
+            let (row, column) = ((index % dim) as usize, (index / dim) as usize);
+            amplitudes
+                .iter()
+                .fold(Complex::zero(), |sum, (weight, amplitude)| {
+                    // p * ψ[row] * conj(ψ[column])
+                    let (a, b) = (amplitude[row], amplitude[column]);
+                    Complex::new(
+                        sum.real + weight * (a.real * b.real + a.imag * b.imag),
+                        sum.imag + weight * (a.imag * b.real - a.real * b.imag),
+                    )
+                })
+        });
+        self
+    }
+
     pub fn init_debug_state(&mut self) -> &mut Self {
         unsafe {
             ffi::initDebugState(self.reg);
@@ -89,6 +234,40 @@ impl<'a> QuReg<'a> {
         self
     }
 
+    /// Initialise the register to the GHZ state `(|0...0> + |1...1>)/sqrt(2)`.
+    pub fn init_ghz_state(&mut self) -> &mut Self {
+        self.init_zero_state().hadamard(0);
+        for qubit in 1..self.reg.numQubitsRepresented {
+            self.controlled_not(qubit - 1, qubit);
+        }
+        self
+    }
+
+    /// Initialise the register to the W state, the uniform superposition of
+    /// every basis state with exactly one qubit set.
+    ///
+    /// Starting from qubit 0 set, each step uses a controlled rotation and a
+    /// CNOT to move all but a `1/num_qubits` share of the probability onto
+    /// the next qubit.
+    pub fn init_w_state(&mut self) -> &mut Self {
+        let num_qubits = self.reg.numQubitsRepresented;
+        self.init_classical_state(1);
+        for qubit in 0..num_qubits - 1 {
+            let share = 1.0 / (num_qubits - qubit) as QReal;
+            self.controlled_rotate_y(qubit, qubit + 1, 2.0 * share.sqrt().acos())
+                .controlled_not(qubit + 1, qubit);
+        }
+        self
+    }
+
+    /// Initialise the register to the zero state, except for the Bell pair
+    /// `(|00> + |11>)/sqrt(2)` on `qubit_one` and `qubit_two`.
+    pub fn init_bell_pair(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
+        self.init_zero_state()
+            .hadamard(qubit_one)
+            .controlled_not(qubit_one, qubit_two)
+    }
+
     pub fn init_state_from_amplitudes(
         &mut self,
         reals: Vec<QReal>,
@@ -104,6 +283,153 @@ impl<'a> QuReg<'a> {
         self
     }
 
+    /// Amplitude-encode a probability distribution, setting the amplitude of
+    /// each basis state to the square root of its probability.
+    ///
+    /// Panics unless there is a nonnegative probability for every basis state
+    /// and they sum to 1 to within `DEFAULT_TOLERANCE`. Use `init_from_unnormalised_probabilities` to
+    /// rescale the distribution instead.
+    pub fn init_from_probabilities(&mut self, probabilities: &[QReal]) -> &mut Self {
+        let total = self.check_probability_distribution(probabilities);
+        if (total - 1.0).abs() > DEFAULT_TOLERANCE {
+            panic!("Probabilities sum to {} rather than 1.", total);
+        }
+
+        self.encode_probabilities(probabilities, 1.0)
+    }
+
+    /// Like `init_from_probabilities`, but rescale the probabilities so they
+    /// sum to 1. Returns their original sum, so that callers can warn about
+    /// distributions that were far from normalised.
+    pub fn init_from_unnormalised_probabilities(&mut self, probabilities: &[QReal]) -> QReal {
+        let total = self.check_probability_distribution(probabilities);
+        if total <= 0.0 {
+            panic!("Probabilities sum to {}, so can't be normalised.", total);
+        }
+
+        self.encode_probabilities(probabilities, total);
+        total
+    }
+
+    fn check_probability_distribution(&self, probabilities: &[QReal]) -> QReal {
+        if self.is_density_matrix() {
+            panic!("Probabilities can only be amplitude-encoded into a state vector.");
+        }
+        let num_amps = 1usize << self.reg.numQubitsRepresented;
+        if probabilities.len() != num_amps {
+            panic!(
+                "Expected {} probabilities, one per basis state, but got {}.",
+                num_amps,
+                probabilities.len()
+            );
+        }
+        if let Some(index) = probabilities.iter().position(|p| p.is_nan() || *p < 0.0) {
+            panic!(
+                "Probability of |{}> is {}, which isn't a valid probability.",
+                index, probabilities[index]
+            );
+        }
+
+        probabilities.iter().sum()
+    }
+
+    fn encode_probabilities(&mut self, probabilities: &[QReal], total: QReal) -> &mut Self {
+        let reals = probabilities.iter().map(|p| (p / total).sqrt()).collect();
+        let imags = vec![0.0; probabilities.len()];
+        self.init_state_from_amplitudes(reals, imags)
+    }
+
+    /// Initialise a state vector with the given amplitudes of some basis
+    /// states, and zero amplitude for all others.
+    ///
+    /// The amplitudes are set as given, so call `normalize` afterwards if
+    /// they aren't already normalised. Panics if an index is out of range.
+    pub fn init_sparse(&mut self, entries: &[(i64, Complex)]) -> &mut Self {
+        if self.is_density_matrix() {
+            panic!("Sparse amplitudes can only be set on a state vector.");
+        }
+        let num_amps = self.num_amps_total();
+        if let Some((index, _)) = entries
+            .iter()
+            .find(|(index, _)| *index < 0 || *index >= num_amps)
+        {
+            panic!(
+                "Basis state {} is out of range for a register with {} amplitudes.",
+                index, num_amps
+            );
+        }
+
+        self.init_blank_state();
+        for (index, amplitude) in entries {
+            self.set_amplitudes(*index, vec![amplitude.real], vec![amplitude.imag]);
+        }
+        self
+    }
+
+    /// Initialise a state vector to a Haar-random pure state, drawn from
+    /// `rng` so that seeded tests are reproducible.
+    ///
+    /// Every amplitude is sampled from a complex Gaussian and the state is
+    /// then normalised. In a distributed environment, `rng` should be seeded
+    /// identically on every rank.
+    pub fn init_random_pure_state(&mut self, rng: &mut impl RngCore) -> &mut Self {
+        if self.is_density_matrix() {
+            panic!("A random pure state can only be drawn into a state vector.");
+        }
+
+        let dim = 1usize << self.reg.numQubitsRepresented;
+        let amplitudes: Vec<_> = (0..dim).map(|_| linalg::complex_gaussian(rng)).collect();
+        let norm = amplitudes
+            .iter()
+            .map(|amplitude| amplitude.real * amplitude.real + amplitude.imag * amplitude.imag)
+            .sum::<QReal>()
+            .sqrt();
+        let (reals, imags) = amplitudes
+            .iter()
+            .map(|amplitude| (amplitude.real / norm, amplitude.imag / norm))
+            .unzip();
+        self.init_state_from_amplitudes(reals, imags)
+    }
+
+    /// Initialise this state vector to the product state of `factors`, with
+    /// `factors[0]` on the lowest-numbered qubits, followed by `factors[1]`
+    /// and so on. The factors must have as many qubits in total as this
+    /// register.
+    pub fn tensor_from(&mut self, factors: &[&QuReg]) -> &mut Self {
+        if self.is_density_matrix() || factors.iter().any(|factor| factor.is_density_matrix()) {
+            panic!("Tensor products can only be built from state vectors.");
+        }
+        let total_qubits: i32 = factors.iter().map(|factor| factor.num_qubits()).sum();
+        if total_qubits != self.num_qubits() {
+            panic!(
+                "Factors have {} qubits in total, but the register has {}.",
+                total_qubits,
+                self.num_qubits()
+            );
+        }
+
+        let mut product = vec![Complex::real(1.0)];
+        for factor in factors {
+            let dim = 1i64 << factor.num_qubits();
+            let mut next = Vec::with_capacity(product.len() * dim as usize);
+            for a in (0..dim).map(|index| factor.amplitude(index)) {
+                next.extend(product.iter().map(|b| {
+                    Complex::new(
+                        a.real * b.real - a.imag * b.imag,
+                        a.real * b.imag + a.imag * b.real,
+                    )
+                }));
+            }
+            product = next;
+        }
+
+        let (reals, imags) = product
+            .iter()
+            .map(|amplitude| (amplitude.real, amplitude.imag))
+            .unzip();
+        self.init_state_from_amplitudes(reals, imags)
+    }
+
     pub fn set_amplitudes(
         &mut self,
         start_index: i64,
@@ -152,10 +478,18 @@ impl<'a> QuReg<'a> {
     // ---------
 
     pub fn phase_shift(&mut self, target_qubit: i32, angle: QReal) -> &mut Self {
-        unsafe {
-            ffi::phaseShift(self.reg, target_qubit, angle);
-        }
-        self
+        self.timed("phase_shift", |reg| unsafe {
+            ffi::phaseShift(reg, target_qubit, angle);
+        })
+    }
+
+    /// Multiply every amplitude in which `control_qubit` is 1 by
+    /// `exp(i phase)`, i.e. a global phase on that branch of the register.
+    ///
+    /// This is the same operation as `phase_shift` on the control qubit, but
+    /// reads better where the relative phase between branches is the point.
+    pub fn controlled_phase(&mut self, control_qubit: i32, phase: QReal) -> &mut Self {
+        self.phase_shift(control_qubit, phase)
     }
 
     pub fn controlled_phase_shift(
@@ -164,10 +498,9 @@ impl<'a> QuReg<'a> {
         qubit_two: i32,
         angle: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledPhaseShift(self.reg, qubit_one, qubit_two, angle);
-        }
-        self
+        self.timed("controlled_phase_shift", |reg| unsafe {
+            ffi::controlledPhaseShift(reg, qubit_one, qubit_two, angle);
+        })
     }
 
     pub fn multi_controlled_phase_shift(
@@ -175,45 +508,91 @@ impl<'a> QuReg<'a> {
         control_qubits: Vec<i32>,
         angle: QReal,
     ) -> &mut Self {
-        unsafe {
+        self.timed("multi_controlled_phase_shift", |reg| unsafe {
             ffi::multiControlledPhaseShift(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
                 angle,
             );
-        }
-        self
+        })
     }
 
     pub fn controlled_phase_flip(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
-        unsafe {
-            ffi::controlledPhaseFlip(self.reg, qubit_one, qubit_two);
-        }
-        self
+        self.timed("controlled_phase_flip", |reg| unsafe {
+            ffi::controlledPhaseFlip(reg, qubit_one, qubit_two);
+        })
     }
 
     pub fn multi_controlled_phase_flip(&mut self, control_qubits: Vec<i32>) -> &mut Self {
-        unsafe {
+        self.timed("multi_controlled_phase_flip", |reg| unsafe {
             ffi::multiControlledPhaseFlip(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
             );
-        }
-        self
+        })
+    }
+
+    /// Controlled-Z between two qubits. This is `controlled_phase_flip` under
+    /// its more common name, and is symmetric in the two qubits.
+    pub fn controlled_z(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
+        self.controlled_phase_flip(qubit_one, qubit_two)
+    }
+
+    /// Multi-qubit controlled-Z, which negates the amplitudes in which all of
+    /// `qubits` are 1. This is `multi_controlled_phase_flip` under its more
+    /// common name.
+    pub fn multi_controlled_z(&mut self, qubits: &[i32]) -> &mut Self {
+        self.multi_controlled_phase_flip(qubits.to_vec())
     }
 
     pub fn apply_s_gate(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::sGate(self.reg, target_qubit);
-        }
-        self
+        self.timed("apply_s_gate", |reg| unsafe {
+            ffi::sGate(reg, target_qubit);
+        })
     }
 
     pub fn apply_t_gate(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::tGate(self.reg, target_qubit);
+        self.timed("apply_t_gate", |reg| unsafe {
+            ffi::tGate(reg, target_qubit);
+        })
+    }
+
+    /// The inverse of `apply_s_gate`, a phase shift of `-π/2`.
+    pub fn sdg(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("sdg", |reg| unsafe {
+            ffi::phaseShift(reg, target_qubit, -PI as QReal / 2.0);
+        })
+    }
+
+    /// The inverse of `apply_t_gate`, a phase shift of `-π/4`.
+    pub fn tdg(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("tdg", |reg| unsafe {
+            ffi::phaseShift(reg, target_qubit, -PI as QReal / 4.0);
+        })
+    }
+
+    /// The time spent in each kind of gate applied to this register since it
+    /// was created or the profile was last reset. This is empty unless
+    /// profiling is enabled on the environment.
+    pub fn profile_report(&self) -> ProfileReport {
+        self.profile.clone()
+    }
+
+    pub fn reset_profile(&mut self) -> &mut Self {
+        self.profile = ProfileReport::default();
+        self
+    }
+
+    // Apply a gate to the underlying register, timing it as `gate` if
+    // profiling is enabled.
+    fn timed(&mut self, gate: &'static str, apply: impl FnOnce(ffi::Qureg)) -> &mut Self {
+        let reg = self.reg;
+        if self.env.is_profiling() {
+            self.profile.time(gate, || apply(reg));
+        } else {
+            apply(reg);
         }
         self
     }
@@ -249,6 +628,26 @@ impl<'a> QuReg<'a> {
         self
     }
 
+    /// Describe the register's size and distribution in the same terms as
+    /// `report_params`, but return it instead of printing to stdout.
+    pub fn params_string(&self) -> String {
+        let num_amps_total = self.reg.numAmpsTotal;
+        let state_bytes = 2 * num_amps_total as u64 * std::mem::size_of::<QReal>() as u64;
+        format!(
+            "QUBITS:\n\
+             Number of qubits is {}.\n\
+             Number of amps is {}.\n\
+             Number of amps per rank is {}.\n\
+             Number of ranks is {}.\n\
+             State memory is {} bytes.\n",
+            self.reg.numQubitsInStateVec,
+            num_amps_total,
+            self.reg.numAmpsPerChunk,
+            self.reg.numChunks,
+            state_bytes,
+        )
+    }
+
     pub fn copy_state_to_gpu(&mut self) -> &mut Self {
         unsafe {
             ffi::copyStateToGPU(self.reg);
@@ -263,6 +662,110 @@ impl<'a> QuReg<'a> {
         self
     }
 
+    /// Read the full density matrix, indexed as `[row][column]`.
+    pub(crate) fn density_matrix(&self) -> Vec<Vec<Complex>> {
+        let dim = 1i64 << self.reg.numQubitsRepresented;
+        (0..dim)
+            .map(|row| {
+                (0..dim)
+                    .map(|column| self.density_amplitude(row, column))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Overwrite each amplitude stored by this process with `amplitude(index)`,
+    /// where `index` is the global index into the underlying state vector (for
+    /// density matrices, `row + column * 2^num_qubits`).
+    pub(crate) fn set_local_amplitudes<F>(&mut self, mut amplitude: F)
+    where
+        F: FnMut(i64) -> Complex,
+    {
+        self.map_local_amplitudes(|index, _| amplitude(index));
+    }
+
+    /// Replace each amplitude stored by this process with
+    /// `f(index, amplitude)`, indexed as in `set_local_amplitudes`.
+    ///
+    /// QuEST 3.1 has no way to set density-matrix elements, so this edits the
+    /// local chunk of the state directly, syncing it from and back to the GPU.
+    pub(crate) fn map_local_amplitudes<F>(&mut self, mut f: F)
+    where
+        F: FnMut(i64, Complex) -> Complex,
+    {
+        self.copy_state_from_gpu();
+        let first_index = self.reg.chunkId as i64 * self.reg.numAmpsPerChunk;
+        for local_index in 0..self.reg.numAmpsPerChunk {
+            unsafe {
+                let real = self.reg.stateVec.real.offset(local_index as isize);
+                let imag = self.reg.stateVec.imag.offset(local_index as isize);
+                let value = f(first_index + local_index, Complex::new(*real, *imag));
+                *real = value.real;
+                *imag = value.imag;
+            }
+        }
+        self.copy_state_to_gpu();
+    }
+
+    /// The real parts of the amplitudes stored by this process, borrowed from
+    /// QuEST's own state array without copying, indexed as in `apply_amp_fn`
+    /// but from the start of this process's chunk.
+    ///
+    /// In a distributed environment this is only the chunk of
+    /// `num_amps_per_chunk` amplitudes that this rank holds, starting at
+    /// `rank * num_amps_per_chunk`. In a GPU build the state lives on the
+    /// device, so call `copy_state_from_gpu` first.
+    pub fn local_amps_real(&self) -> &[QReal] {
+        unsafe {
+            std::slice::from_raw_parts(self.reg.stateVec.real, self.reg.numAmpsPerChunk as usize)
+        }
+    }
+
+    /// The imaginary parts of the amplitudes stored by this process, as in
+    /// `local_amps_real`.
+    pub fn local_amps_imag(&self) -> &[QReal] {
+        unsafe {
+            std::slice::from_raw_parts(self.reg.stateVec.imag, self.reg.numAmpsPerChunk as usize)
+        }
+    }
+
+    /// The real parts of the amplitudes stored by this process, borrowed
+    /// mutably for bulk edits in place, indexed as in `local_amps_real`.
+    ///
+    /// This writes straight into QuEST's state array, bypassing all of its
+    /// bookkeeping and validation: nothing keeps the state normalised (see
+    /// `normalize`), density matrices Hermitian, or QASM recording in step.
+    /// In a GPU build, call `copy_state_from_gpu` before editing and
+    /// `mark_state_dirty` afterwards, or the edits are lost. In a distributed
+    /// environment, each rank only sees its own chunk.
+    pub fn local_amps_real_mut(&mut self) -> &mut [QReal] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.reg.stateVec.real,
+                self.reg.numAmpsPerChunk as usize,
+            )
+        }
+    }
+
+    /// The imaginary parts of the amplitudes stored by this process, with the
+    /// same caveats as `local_amps_real_mut`.
+    pub fn local_amps_imag_mut(&mut self) -> &mut [QReal] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.reg.stateVec.imag,
+                self.reg.numAmpsPerChunk as usize,
+            )
+        }
+    }
+
+    /// Push edits made through `local_amps_real_mut` or `local_amps_imag_mut`
+    /// to the GPU, which other operations read the state from in a GPU build.
+    /// Elsewhere this does nothing.
+    pub fn mark_state_dirty(&mut self) -> &mut Self {
+        self.copy_state_to_gpu()
+    }
+
+    /// Get the complex probability amplitude of the basis state at `index`.
     pub fn amplitude(&self, index: i64) -> Complex {
         unsafe { ffi::getAmp(self.reg, index).into() }
     }
@@ -275,38 +778,452 @@ impl<'a> QuReg<'a> {
         unsafe { ffi::getImagAmp(self.reg, index) }
     }
 
-    pub fn probability_amplitude(&self, index: i64) -> QReal {
+    /// Get the probability of measuring the basis state at `index`, i.e. the
+    /// squared magnitude of its amplitude.
+    pub fn probability_of_state(&self, index: i64) -> QReal {
         unsafe { ffi::getProbAmp(self.reg, index) }
     }
 
+    /// Find the basis state with the largest probability, and that
+    /// probability, without measuring the register. Ties go to the lowest
+    /// index.
+    ///
+    /// Every probability is read through QuEST, which broadcasts it from the
+    /// rank that stores it, so distributed registers give the global maximum.
+    pub fn most_probable_state(&self) -> (i64, QReal) {
+        self.diagonal()
+            .into_iter()
+            .enumerate()
+            .map(|(index, probability)| (index as i64, probability))
+            .fold((0, -1.0), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// The probability of measuring each basis state, which for a density
+    /// matrix are its diagonal elements (the populations).
+    pub fn diagonal(&self) -> Vec<QReal> {
+        let dim = 1i64 << self.reg.numQubitsRepresented;
+        (0..dim)
+            .map(|index| {
+                if self.is_density_matrix() {
+                    self.density_amplitude(index, index).real
+                } else {
+                    self.probability_of_state(index)
+                }
+            })
+            .collect()
+    }
+
+    /// The joint probability distribution of measuring `qubits`, with
+    /// `qubits[0]` as the least significant bit of the outcome index.
+    pub fn marginal_probabilities(&self, qubits: &[i32]) -> Vec<QReal> {
+        let num_qubits = self.reg.numQubitsRepresented;
+        for (i, qubit) in qubits.iter().enumerate() {
+            if *qubit < 0 || *qubit >= num_qubits {
+                panic!("Qubit {} is outside of the register.", qubit);
+            }
+            if qubits[..i].contains(qubit) {
+                panic!("Qubit {} appears more than once.", qubit);
+            }
+        }
+
+        let mut marginals = vec![0.0; 1 << qubits.len()];
+        for (index, probability) in self.diagonal().into_iter().enumerate() {
+            let outcome = qubits.iter().enumerate().fold(0, |outcome, (bit, &qubit)| {
+                outcome | ((index >> qubit) & 1) << bit
+            });
+            marginals[outcome] += probability;
+        }
+        marginals
+    }
+
+    /// The probability that measuring each of `qubits` would give the
+    /// matching entry of `outcomes`, without collapsing the register.
+    pub fn probability_of_bitstring(&self, qubits: &[i32], outcomes: &[i32]) -> QReal {
+        if qubits.len() != outcomes.len() {
+            panic!(
+                "Got {} outcomes for {} qubits, but need one outcome per qubit.",
+                outcomes.len(),
+                qubits.len()
+            );
+        }
+        if let Some(outcome) = outcomes
+            .iter()
+            .find(|outcome| **outcome != 0 && **outcome != 1)
+        {
+            panic!("Outcome {} is not 0 or 1.", outcome);
+        }
+
+        let index = outcomes
+            .iter()
+            .enumerate()
+            .fold(0, |index, (bit, &outcome)| {
+                index | (outcome as usize) << bit
+            });
+        self.marginal_probabilities(qubits)[index]
+    }
+
+    /// Half the L1 distance between the measurement distributions of this
+    /// register and `other`: 0 for identical distributions and 1 for ones
+    /// with disjoint support.
+    pub fn total_variation_distance(&self, other: &QuReg) -> QReal {
+        self.check_same_num_qubits(other);
+        self.diagonal()
+            .into_iter()
+            .zip(other.diagonal())
+            .map(|(p, q)| (p - q).abs())
+            .sum::<QReal>()
+            / 2.0
+    }
+
+    /// The Kullback-Leibler divergence `D(p || q)` in nats, from the
+    /// measurement distribution `q` of `other` to the distribution `p` of
+    /// this register.
+    ///
+    /// This is infinite if `other` can never give an outcome this register
+    /// can.
+    pub fn kl_divergence(&self, other: &QuReg) -> QReal {
+        self.check_same_num_qubits(other);
+        self.diagonal()
+            .into_iter()
+            .zip(other.diagonal())
+            .filter(|(p, _)| *p > 0.0)
+            .map(|(p, q)| p * (p / q).ln())
+            .sum()
+    }
+
+    fn check_same_num_qubits(&self, other: &QuReg) {
+        if self.num_qubits() != other.num_qubits() {
+            panic!(
+                "Registers of {} and {} qubits can't be compared.",
+                self.num_qubits(),
+                other.num_qubits()
+            );
+        }
+    }
+
+    #[deprecated(
+        since = "0.2.9",
+        note = "this returns a probability, not an amplitude; use `probability_of_state` or `amplitude` instead"
+    )]
+    pub fn probability_amplitude(&self, index: i64) -> QReal {
+        self.probability_of_state(index)
+    }
+
     pub fn density_amplitude(&self, row_index: i64, column_index: i64) -> Complex {
         unsafe { ffi::getDensityAmp(self.reg, row_index, column_index).into() }
     }
 
+    /// The amplitudes of a state vector as an `ndarray` array, indexed by
+    /// basis state. Use `to_ndarray_matrix` for a density matrix.
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> ndarray::Array1<Complex> {
+        if self.is_density_matrix() {
+            panic!("Register is a density matrix, so use `to_ndarray_matrix`.");
+        }
+
+        let dim = 1i64 << self.reg.numQubitsRepresented;
+        let amplitudes: Vec<_> = (0..dim).map(|index| self.amplitude(index)).collect();
+        ndarray::Array1::from(amplitudes)
+    }
+
+    /// The elements of a density matrix as an `ndarray` array, indexed by
+    /// `[row, column]`.
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray_matrix(&self) -> ndarray::Array2<Complex> {
+        if !self.is_density_matrix() {
+            panic!("Register is a state vector, so use `to_ndarray`.");
+        }
+
+        let dim = 1usize << self.reg.numQubitsRepresented;
+        ndarray::Array2::from_shape_fn((dim, dim), |(row, column)| {
+            self.density_amplitude(row as i64, column as i64)
+        })
+    }
+
+    /// The total probability of the register, which should be 1 unless it has
+    /// been unnormalised.
+    ///
+    /// QuEST reduces this over every rank, so in a distributed environment it
+    /// covers the whole register rather than only the locally-stored chunk.
     pub fn calculate_total_probability(&self) -> QReal {
         unsafe { ffi::calcTotalProb(self.reg) }
     }
 
+    /// Check whether the total probability is within `tolerance` of 1.
+    pub fn is_normalized(&self, tolerance: QReal) -> bool {
+        (self.calculate_total_probability() - 1.0).abs() <= tolerance
+    }
+
+    /// Replace each amplitude with `f(index, amplitude)`, for manipulations
+    /// no gate covers. For a density matrix, `index` is `row + column *
+    /// 2^num_qubits`. In a distributed environment, each process only maps
+    /// the amplitudes it stores.
+    ///
+    /// Nothing checks that `f` is unitary, so the register may need to be
+    /// normalised afterwards with `normalize`.
+    pub fn apply_amp_fn<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(i64, Complex) -> Complex,
+    {
+        self.map_local_amplitudes(f);
+        self
+    }
+
+    /// Rescale the register so its total probability is 1, e.g. after
+    /// setting amplitudes by hand. State vectors are divided by the square
+    /// root of their total probability and density matrices by their trace.
+    pub fn normalize(&mut self) -> &mut Self {
+        let total_probability = self.calculate_total_probability();
+        if total_probability <= 0.0 {
+            panic!("Can't normalise a register with zero total probability.");
+        }
+
+        let factor = if self.is_density_matrix() {
+            1.0 / total_probability
+        } else {
+            1.0 / total_probability.sqrt()
+        };
+        self.map_local_amplitudes(|_, amplitude| {
+            Complex::new(amplitude.real * factor, amplitude.imag * factor)
+        });
+        self
+    }
+
+    /// Multiply the amplitude of each basis state `|x>` by `exp(i f(x))`,
+    /// e.g. to apply the cost phase of a QAOA layer.
+    ///
+    /// QuEST 3.1 has no diagonal operators, so the phases are applied to the
+    /// locally stored amplitudes directly. For a density matrix, element
+    /// `(r, c)` picks up `exp(i (f(r) - f(c)))`.
+    pub fn apply_diagonal_fn<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(i64) -> QReal,
+    {
+        let phase = |index: i64| {
+            let angle = f(index);
+            Complex::new(angle.cos(), angle.sin())
+        };
+        let rotate = |amplitude: Complex, phase: Complex| {
+            Complex::new(
+                amplitude.real * phase.real - amplitude.imag * phase.imag,
+                amplitude.real * phase.imag + amplitude.imag * phase.real,
+            )
+        };
+
+        if self.is_density_matrix() {
+            let dim = 1i64 << self.reg.numQubitsRepresented;
+            self.map_local_amplitudes(|index, amplitude| {
+                let (row, column) = (phase(index % dim), phase(index / dim));
+                let conjugated = Complex::new(column.real, -column.imag);
+                rotate(rotate(amplitude, row), conjugated)
+            });
+        } else {
+            self.map_local_amplitudes(|index, amplitude| rotate(amplitude, phase(index)));
+        }
+        self
+    }
+
+    /// Like `apply_diagonal_fn`, but only basis states in which every one of
+    /// `control_qubits` is 1 pick up a phase, e.g. for a controlled cost
+    /// phase in phase estimation. `f` is still given the whole basis index.
+    pub fn apply_controlled_diagonal_fn<F>(&mut self, control_qubits: &[i32], f: F) -> &mut Self
+    where
+        F: Fn(i64) -> QReal,
+    {
+        let num_qubits = self.reg.numQubitsRepresented;
+        if let Some(qubit) = control_qubits
+            .iter()
+            .find(|qubit| **qubit < 0 || **qubit >= num_qubits)
+        {
+            panic!("Control qubit {} is outside of the register.", qubit);
+        }
+
+        let mask = control_qubits
+            .iter()
+            .fold(0i64, |mask, &qubit| mask | 1 << qubit);
+        self.apply_diagonal_fn(|index| if index & mask == mask { f(index) } else { 0.0 })
+    }
+
+    /// Multiply each basis state `|x>` in which every one of `control_qubits`
+    /// is 1 by `exp(i f(x))`, leaving the rest of the state alone, e.g. for a
+    /// conditional cost oracle. This is `apply_controlled_diagonal_fn`.
+    pub fn apply_controlled_phase_fn<F>(&mut self, control_qubits: &[i32], f: F) -> &mut Self
+    where
+        F: Fn(i64) -> QReal,
+    {
+        self.apply_controlled_diagonal_fn(control_qubits, f)
+    }
+
+    /// Negate the amplitude of each of `marked_states`, as the phase oracle
+    /// of a Grover search with several solutions.
+    ///
+    /// The signs are flipped in a single pass over the amplitudes, rather
+    /// than with a multi-controlled Z between X gates for every marked state.
+    pub fn apply_phase_oracle(&mut self, marked_states: &[i64]) -> &mut Self {
+        let dim = 1i64 << self.reg.numQubitsRepresented;
+        if let Some(state) = marked_states
+            .iter()
+            .find(|state| **state < 0 || **state >= dim)
+        {
+            panic!("Marked state {} is outside of the register.", state);
+        }
+
+        let mut marked = marked_states.to_vec();
+        marked.sort_unstable();
+        let is_marked = |index: i64| marked.binary_search(&index).is_ok();
+        let negate = |amplitude: Complex| Complex::new(-amplitude.real, -amplitude.imag);
+
+        if self.is_density_matrix() {
+            self.map_local_amplitudes(|index, amplitude| {
+                if is_marked(index % dim) != is_marked(index / dim) {
+                    negate(amplitude)
+                } else {
+                    amplitude
+                }
+            });
+        } else {
+            self.map_local_amplitudes(|index, amplitude| {
+                if is_marked(index) {
+                    negate(amplitude)
+                } else {
+                    amplitude
+                }
+            });
+        }
+        self
+    }
+
+    /// Apply one QAOA layer: the cost unitary `exp(-i gamma C)` for the
+    /// diagonal cost function `cost`, then the mixer `exp(-i beta X)` on every
+    /// qubit.
+    pub fn apply_qaoa_layer<F>(&mut self, cost: F, gamma: QReal, beta: QReal) -> &mut Self
+    where
+        F: Fn(i64) -> QReal,
+    {
+        self.apply_diagonal_fn(|index| -gamma * cost(index))
+            .apply_to_all(|qureg, qubit| {
+                qureg.rotate_x(qubit, 2.0 * beta);
+            })
+    }
+
+    /// Multiply a state vector by a global phase so that its first amplitude
+    /// with magnitude above `DEFAULT_TOLERANCE` is real and positive. Density
+    /// matrices have no global phase, so are left unchanged.
+    pub fn fix_global_phase(&mut self) -> &mut Self {
+        if self.is_density_matrix() {
+            return self;
+        }
+
+        let dim = 1i64 << self.reg.numQubitsRepresented;
+        let first = (0..dim)
+            .map(|index| self.amplitude(index))
+            .find(|amplitude| amplitude.real.hypot(amplitude.imag) > DEFAULT_TOLERANCE);
+        if let Some(first) = first {
+            let norm = first.real.hypot(first.imag);
+            let (real, imag) = (first.real / norm, -first.imag / norm);
+            self.map_local_amplitudes(|_, amplitude| {
+                Complex::new(
+                    amplitude.real * real - amplitude.imag * imag,
+                    amplitude.real * imag + amplitude.imag * real,
+                )
+            });
+        }
+        self
+    }
+
     pub fn calculate_purity(&self) -> QReal {
         unsafe { ffi::calcPurity(self.reg) }
     }
 
+    /// Linear entropy `1 - Tr(rho^2)`, which is zero for pure states and
+    /// `1 - 1/2^n` for the maximally mixed state on `n` qubits.
+    pub fn linear_entropy(&self) -> QReal {
+        if self.is_density_matrix() {
+            1.0 - self.calculate_purity()
+        } else {
+            0.0
+        }
+    }
+
+    /// Von Neumann entropy `-Tr(rho log2 rho)` in bits, computed from the
+    /// eigenvalues of the density matrix.
+    ///
+    /// This reads the whole density matrix into memory and diagonalises it, so
+    /// is only practical for small registers. Eigenvalues below
+    /// `DEFAULT_TOLERANCE` are taken to be rounding error and skipped. State
+    /// vectors are pure and so always have zero entropy.
+    pub fn von_neumann_entropy(&self) -> QReal {
+        if !self.is_density_matrix() {
+            return 0.0;
+        }
+
+        linalg::hermitian_eigenvalues(&self.density_matrix())
+            .into_iter()
+            .filter(|eigenvalue| *eigenvalue > DEFAULT_TOLERANCE)
+            .map(|eigenvalue| -eigenvalue * eigenvalue.log2())
+            .sum()
+    }
+
     pub fn calculate_fidelity(&self, pure_state: &QuReg) -> QReal {
         unsafe { ffi::calcFidelity(self.reg, pure_state.reg) }
     }
 
+    /// The Uhlmann fidelity `(Tr sqrt(sqrt(rho) sigma sqrt(rho)))^2` between
+    /// two density matrices, neither of which needs to be pure, unlike for
+    /// `calculate_fidelity`.
+    ///
+    /// Like `von_neumann_entropy`, this reads both density matrices into
+    /// memory, so is only practical for small registers.
+    pub fn uhlmann_fidelity(&self, other: &QuReg) -> QReal {
+        self.assert_density();
+        other.assert_density();
+        self.check_same_num_qubits(other);
+        linalg::uhlmann_fidelity(&self.density_matrix(), &other.density_matrix())
+    }
+
     pub fn calculate_expected_pauli_product(
         &self,
         target_qubits: Vec<i32>,
         target_paulis: Vec<PauliOpType>,
         workspace: QuReg,
+    ) -> QReal {
+        self.expected_pauli_product(&target_qubits, &target_paulis, &workspace)
+    }
+
+    /// Calculate the expected value of each of a batch of Pauli products,
+    /// given as `(target_qubits, target_paulis)` pairs, reusing `workspace`
+    /// for all of them instead of allocating one per term.
+    pub fn calculate_expected_pauli_products(
+        &self,
+        terms: &[(Vec<i32>, Vec<PauliOpType>)],
+        workspace: &mut QuReg,
+    ) -> Vec<QReal> {
+        terms
+            .iter()
+            .map(|(target_qubits, target_paulis)| {
+                self.expected_pauli_product(target_qubits, target_paulis, workspace)
+            })
+            .collect()
+    }
+
+    fn expected_pauli_product(
+        &self,
+        target_qubits: &[i32],
+        target_paulis: &[PauliOpType],
+        workspace: &QuReg,
     ) -> QReal {
         if target_qubits.len() != target_paulis.len() {
             panic!("Number of target qubits must be the same as number of target Pauli operation types");
         }
 
         let ffi_target_paulis: Vec<ffi::pauliOpType> =
-            target_paulis.into_iter().map(Into::into).collect();
+            target_paulis.iter().copied().map(Into::into).collect();
 
         unsafe {
             ffi::calcExpecPauliProd(
@@ -343,24 +1260,265 @@ impl<'a> QuReg<'a> {
         }
     }
 
-    pub fn calculate_probability_of_outcome(&self, measure_qubit: i32, outcome: i32) -> QReal {
-        unsafe { ffi::calcProbOfOutcome(self.reg, measure_qubit, outcome) }
-    }
+    /// Calculate `<ψ|O|ψ>` for an arbitrary, not necessarily Hermitian,
+    /// operator `O` on `target_qubits`, where `target_qubits[0]` is the least
+    /// significant bit of the matrix index.
+    ///
+    /// QuEST 3.1 can only apply unitary matrices, so `O|ψ>` is computed into
+    /// `workspace` here before taking its inner product with the register.
+    /// Only state vectors are supported.
+    pub fn expectation_matrix(
+        &self,
+        target_qubits: &[i32],
+        observable: &ComplexMatrixN,
+        workspace: &mut QuReg,
+    ) -> Complex {
+        if self.is_density_matrix() || workspace.is_density_matrix() {
+            panic!("Expectation values of matrices can only be taken of state vectors.");
+        }
+        if workspace.reg.numQubitsRepresented != self.reg.numQubitsRepresented {
+            panic!("Workspace must have the same number of qubits as the register.");
+        }
+        assert_targets(target_qubits, self.reg.numQubitsRepresented);
+        assert_matrix_size(observable, target_qubits.len());
 
-    pub fn calculate_hilbert_schmidt_distance(&mut self, to_density_matrix: &QuReg) -> QReal {
-        unsafe { ffi::calcHilbertSchmidtDistance(self.reg, to_density_matrix.reg) }
+        let amplitudes: Vec<Complex> = (0..self.reg.numAmpsTotal)
+            .map(|index| self.amplitude(index))
+            .collect();
+        let target_mask: i64 = target_qubits.iter().map(|&qubit| 1i64 << qubit).sum();
+        let with_targets = |index: i64, sub_index: usize| -> i64 {
+            target_qubits
+                .iter()
+                .enumerate()
+                .fold(index & !target_mask, |index, (bit, &qubit)| {
+                    index | ((sub_index as i64 >> bit) & 1) << qubit
+                })
+        };
+
+        workspace.set_local_amplitudes(|index| {
+            let row = (0..target_qubits.len())
+                .map(|bit| ((index >> target_qubits[bit]) & 1) << bit)
+                .sum::<i64>() as usize;
+            (0..observable.dim()).fold(Complex::zero(), |sum, column| {
+                let element = observable.get(row, column);
+                let amplitude = amplitudes[with_targets(index, column) as usize];
+                Complex::new(
+                    sum.real + element.real * amplitude.real - element.imag * amplitude.imag,
+                    sum.imag + element.real * amplitude.imag + element.imag * amplitude.real,
+                )
+            })
+        });
+
+        unsafe { ffi::calcInnerProduct(self.reg, workspace.reg) }.into()
+    }
+
+    /// The Bloch vector `(<X>, <Y>, <Z>)` of a single qubit's reduced state.
+    ///
+    /// Pure states of the qubit lie on the unit sphere, and mixed states
+    /// (including qubits entangled with the rest of the register) inside it.
+    pub fn bloch_vector(&self, qubit: i32) -> Vector {
+        let num_qubits = self.reg.numQubitsRepresented;
+        let workspace = if self.is_density_matrix() {
+            QuReg::new_density(num_qubits, self.env)
+        } else {
+            QuReg::new(num_qubits, self.env)
+        };
+        let expectation =
+            |pauli: PauliOpType| self.expected_pauli_product(&[qubit], &[pauli], &workspace);
+
+        Vector::new(
+            expectation(PauliOpType::PauliX),
+            expectation(PauliOpType::PauliY),
+            expectation(PauliOpType::PauliZ),
+        )
+    }
+
+    pub fn calculate_probability_of_outcome(&self, measure_qubit: i32, outcome: i32) -> QReal {
+        unsafe { ffi::calcProbOfOutcome(self.reg, measure_qubit, outcome) }
+    }
+
+    /// Like `calculate_probability_of_outcome`, but takes the outcome as an
+    /// `Outcome`.
+    pub fn calculate_probability_of(&self, measure_qubit: i32, outcome: Outcome) -> QReal {
+        self.calculate_probability_of_outcome(measure_qubit, outcome.into())
+    }
+
+    pub fn calculate_hilbert_schmidt_distance(&mut self, to_density_matrix: &QuReg) -> QReal {
+        unsafe { ffi::calcHilbertSchmidtDistance(self.reg, to_density_matrix.reg) }
+    }
+
+    /// Create a new density-matrix register holding `|ψ><ψ|` for this state
+    /// vector `|ψ>`, e.g. to go on simulating it under noise.
+    pub fn to_density_matrix<'b>(&self, env: &'b QuestEnv) -> QuReg<'b> {
+        if self.is_density_matrix() {
+            panic!("Register is already a density matrix.");
+        }
+
+        let mut density = QuReg::new_density(self.num_qubits(), env);
+        density.init_pure_state(self);
+        density
+    }
+
+    /// Trace out every qubit not in `keep_qubits`, returning the reduced
+    /// density matrix of the kept subsystem as a new register.
+    ///
+    /// Qubit `keep_qubits[i]` of this register becomes qubit `i` of the
+    /// returned one. This works on both state vectors and density matrices.
+    pub fn reduced_density_matrix<'b>(&self, keep_qubits: &[i32], env: &'b QuestEnv) -> QuReg<'b> {
+        let num_qubits = self.reg.numQubitsRepresented;
+        if keep_qubits.is_empty() {
+            panic!("At least one qubit must be kept when taking a partial trace.");
+        }
+        for (i, qubit) in keep_qubits.iter().enumerate() {
+            if *qubit < 0 || *qubit >= num_qubits {
+                panic!("Kept qubit {} is outside of the register.", qubit);
+            }
+            if keep_qubits[..i].contains(qubit) {
+                panic!("Kept qubit {} appears more than once.", qubit);
+            }
+        }
+
+        let traced_qubits: Vec<i32> = (0..num_qubits)
+            .filter(|qubit| !keep_qubits.contains(qubit))
+            .collect();
+        let spread = |bits: i64, qubits: &[i32]| -> i64 {
+            qubits
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| (bits >> bit) & 1 == 1)
+                .map(|(_, qubit)| 1 << qubit)
+                .sum()
+        };
+
+        let kept_dim = 1i64 << keep_qubits.len();
+        let traced_dim = 1i64 << traced_qubits.len();
+        let state_vector: Vec<Complex> = if self.is_density_matrix() {
+            Vec::new()
+        } else {
+            (0..1i64 << num_qubits).map(|i| self.amplitude(i)).collect()
+        };
+
+        let mut reduced = vec![Complex::zero(); (kept_dim * kept_dim) as usize];
+        for row in 0..kept_dim {
+            for column in 0..kept_dim {
+                let value = &mut reduced[(row + column * kept_dim) as usize];
+                for traced in 0..traced_dim {
+                    let traced_bits = spread(traced, &traced_qubits);
+                    let full_row = spread(row, keep_qubits) | traced_bits;
+                    let full_column = spread(column, keep_qubits) | traced_bits;
+
+                    if self.is_density_matrix() {
+                        let element = self.density_amplitude(full_row, full_column);
+                        value.real += element.real;
+                        value.imag += element.imag;
+                    } else {
+                        // rho_rc = psi_r * conj(psi_c)
+                        let a = state_vector[full_row as usize];
+                        let b = state_vector[full_column as usize];
+                        value.real += a.real * b.real + a.imag * b.imag;
+                        value.imag += a.imag * b.real - a.real * b.imag;
+                    }
+                }
+            }
+        }
+
+        let mut reduced_qureg = QuReg::new_density(keep_qubits.len() as i32, env);
+        reduced_qureg.set_local_amplitudes(|index| reduced[index as usize]);
+        reduced_qureg
+    }
+
+    /// The state of `keep_qubits` alone, with every other qubit marginalised
+    /// out. This is `reduced_density_matrix` under another name: even when
+    /// this register holds a pure state, the kept qubits are generally in a
+    /// mixed one, so the result is always a density matrix.
+    pub fn marginal_state<'b>(&self, keep_qubits: &[i32], env: &'b QuestEnv) -> QuReg<'b> {
+        self.reduced_density_matrix(keep_qubits, env)
     }
 
     pub fn collapse_to_outcome(&mut self, measure_qubit: i32, outcome: i32) -> QReal {
         unsafe { ffi::collapseToOutcome(self.reg, measure_qubit, outcome) }
     }
 
+    /// Like `collapse_to_outcome`, but takes the outcome as an `Outcome`.
+    pub fn collapse_to(&mut self, measure_qubit: i32, outcome: Outcome) -> QReal {
+        self.collapse_to_outcome(measure_qubit, outcome.into())
+    }
+
     pub fn measure(&mut self, measure_qubit: i32) -> i32 {
+        let _rng = lock_rng();
         unsafe { ffi::measure(self.reg, measure_qubit) }
     }
 
+    /// Like `measure`, but returns the outcome as an `Outcome`.
+    pub fn measure_outcome(&mut self, measure_qubit: i32) -> Outcome {
+        Outcome::from_bit(self.measure(measure_qubit))
+    }
+
+    /// Measure a qubit, drawing the outcome from `rng` instead of QuEST's
+    /// internal generator, so that seeded tests are reproducible.
+    ///
+    /// In a distributed environment every rank must draw the same value, so
+    /// `rng` should be seeded identically on each of them.
+    pub fn measure_with_rng(&mut self, measure_qubit: i32, rng: &mut impl RngCore) -> i32 {
+        let zero_probability = self.calculate_probability_of_outcome(measure_qubit, 0);
+        let outcome = if rng.gen::<QReal>() < zero_probability {
+            0
+        } else {
+            1
+        };
+        self.collapse_to_outcome(measure_qubit, outcome);
+        outcome
+    }
+
+    /// Measure a qubit and store the outcome in `bit` of `classical`, as well
+    /// as returning it.
+    pub fn measure_into(
+        &mut self,
+        measure_qubit: i32,
+        classical: &mut ClassicalRegister,
+        bit: usize,
+    ) -> i32 {
+        let outcome = self.measure(measure_qubit);
+        classical.set(bit, outcome);
+        outcome
+    }
+
+    /// Apply X to `target_qubit` if `bit` of `classical` is 1.
+    pub fn x_if(
+        &mut self,
+        classical: &ClassicalRegister,
+        bit: usize,
+        target_qubit: i32,
+    ) -> &mut Self {
+        if classical.get(bit) == 1 {
+            self.pauli_x(target_qubit);
+        }
+        self
+    }
+
+    /// Apply Z to `target_qubit` if `bit` of `classical` is 1.
+    pub fn z_if(
+        &mut self,
+        classical: &ClassicalRegister,
+        bit: usize,
+        target_qubit: i32,
+    ) -> &mut Self {
+        if classical.get(bit) == 1 {
+            self.pauli_z(target_qubit);
+        }
+        self
+    }
+
+    /// Measure each of `qubits` in turn, collapsing the register after each
+    /// one, and return their outcomes in the same order. Other qubits are
+    /// left unmeasured.
+    pub fn measure_qubits(&mut self, qubits: &[i32]) -> Vec<i32> {
+        qubits.iter().map(|&qubit| self.measure(qubit)).collect()
+    }
+
     pub fn measure_with_stats(&mut self, measure_qubit: i32) -> (i32, QReal) {
         let mut outcome_probability = QReal::default();
+        let _rng = lock_rng();
         unsafe {
             let measurement = ffi::measureWithStats(
                 self.reg,
@@ -372,11 +1530,92 @@ impl<'a> QuReg<'a> {
         }
     }
 
+    /// Like `measure_with_stats`, but with the outcome and its probability
+    /// named.
+    pub fn measure_result(&mut self, measure_qubit: i32) -> MeasurementResult {
+        let (outcome, probability) = self.measure_with_stats(measure_qubit);
+        MeasurementResult {
+            outcome,
+            probability,
+        }
+    }
+
+    /// Measure every qubit, returning the outcome of qubit `i` at index `i`
+    /// and the probability the register had of giving that whole bitstring.
+    ///
+    /// The joint probability is the product of the probabilities of each
+    /// outcome, conditioned on the qubits measured before it.
+    pub fn measure_all_with_stats(&mut self) -> (Vec<i32>, QReal) {
+        (0..self.num_qubits()).fold((Vec::new(), 1.0), |(mut outcomes, probability), qubit| {
+            let (outcome, outcome_probability) = self.measure_with_stats(qubit);
+            outcomes.push(outcome);
+            (outcomes, probability * outcome_probability)
+        })
+    }
+
     pub fn unitary(&mut self, target_qubit: i32, unitary_matrix: ComplexMatrix2) -> &mut Self {
-        unsafe {
-            ffi::unitary(self.reg, target_qubit, unitary_matrix.into());
+        self.timed("unitary", |reg| unsafe {
+            ffi::unitary(reg, target_qubit, unitary_matrix.into());
+        })
+    }
+
+    /// Apply the adjoint `U^†` of `unitary_matrix`, e.g. to uncompute an
+    /// earlier `unitary`.
+    pub fn unitary_dagger(
+        &mut self,
+        target_qubit: i32,
+        unitary_matrix: ComplexMatrix2,
+    ) -> &mut Self {
+        self.unitary(target_qubit, unitary_matrix.adjoint())
+    }
+
+    /// Evolve `target_qubit` for time `time` under the Hermitian Hamiltonian
+    /// `hamiltonian`, i.e. apply `exp(-i H t)`.
+    ///
+    /// The exponential is computed in closed form by writing `H` as
+    /// `a_0 I + a·σ`, whose eigenvalues are `a_0 ± |a|`, as
+    /// `exp(-i a_0 t) (cos(|a| t) I - i sin(|a| t) a·σ / |a|)`. `H` must be
+    /// Hermitian to within `DEFAULT_TOLERANCE` on each element.
+    pub fn apply_hermitian_evolution(
+        &mut self,
+        target_qubit: i32,
+        hamiltonian: ComplexMatrix2,
+        time: QReal,
+    ) -> &mut Self {
+        let (real, imag) = (hamiltonian.real, hamiltonian.imag);
+        let is_hermitian = (real[0][1] - real[1][0]).abs() < DEFAULT_TOLERANCE
+            && (imag[0][1] + imag[1][0]).abs() < DEFAULT_TOLERANCE
+            && imag[0][0].abs() < DEFAULT_TOLERANCE
+            && imag[1][1].abs() < DEFAULT_TOLERANCE;
+        if !is_hermitian {
+            panic!("Hamiltonian must be Hermitian to generate a unitary evolution.");
         }
-        self
+
+        let identity_part = (real[0][0] + real[1][1]) / 2.0;
+        let (x, y, z) = (real[1][0], imag[1][0], (real[0][0] - real[1][1]) / 2.0);
+        let norm = (x * x + y * y + z * z).sqrt();
+        let (nx, ny, nz) = if norm > 1e-300 {
+            (x / norm, y / norm, z / norm)
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        let (c, s) = ((norm * time).cos(), (norm * time).sin());
+        let rotation_real = [[c, -s * ny], [s * ny, c]];
+        let rotation_imag = [[-s * nz, -s * nx], [-s * nx, s * nz]];
+
+        // Multiply through by the global phase exp(-i a_0 t).
+        let (phase_real, phase_imag) =
+            ((identity_part * time).cos(), -(identity_part * time).sin());
+        let mut unitary = ComplexMatrix2::real([[0.0; 2]; 2]);
+        for i in 0..2 {
+            for j in 0..2 {
+                let (a, b) = (rotation_real[i][j], rotation_imag[i][j]);
+                unitary.real[i][j] = phase_real * a - phase_imag * b;
+                unitary.imag[i][j] = phase_real * b + phase_imag * a;
+            }
+        }
+        self.unitary(target_qubit, unitary)
     }
 
     pub fn compact_unitary(
@@ -385,10 +1624,9 @@ impl<'a> QuReg<'a> {
         alpha: Complex,
         beta: Complex,
     ) -> &mut Self {
-        unsafe {
-            ffi::compactUnitary(self.reg, target_qubit, alpha.into(), beta.into());
-        }
-        self
+        self.timed("compact_unitary", |reg| unsafe {
+            ffi::compactUnitary(reg, target_qubit, alpha.into(), beta.into());
+        })
     }
 
     pub fn controlled_unitary(
@@ -397,10 +1635,9 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         unitary_matrix: ComplexMatrix2,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledUnitary(self.reg, control_qubit, target_qubit, unitary_matrix.into());
-        }
-        self
+        self.timed("controlled_unitary", |reg| unsafe {
+            ffi::controlledUnitary(reg, control_qubit, target_qubit, unitary_matrix.into());
+        })
     }
 
     pub fn multi_controlled_unitary(
@@ -409,16 +1646,27 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         unitary_matrix: ComplexMatrix2,
     ) -> &mut Self {
-        unsafe {
+        self.timed("multi_controlled_unitary", |reg| unsafe {
             ffi::multiControlledUnitary(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
                 target_qubit,
                 unitary_matrix.into(),
             );
-        }
-        self
+        })
+    }
+
+    /// Like `multi_controlled_unitary`, but returns an error instead of
+    /// aborting if the target is also one of the control qubits.
+    pub fn try_multi_controlled_unitary(
+        &mut self,
+        control_qubits: Vec<i32>,
+        target_qubit: i32,
+        unitary_matrix: ComplexMatrix2,
+    ) -> Result<&mut Self, QuestError> {
+        check_controls_disjoint(&control_qubits, &[target_qubit])?;
+        Ok(self.multi_controlled_unitary(control_qubits, target_qubit, unitary_matrix))
     }
 
     pub fn controlled_compact_unitary(
@@ -428,16 +1676,15 @@ impl<'a> QuReg<'a> {
         alpha: Complex,
         beta: Complex,
     ) -> &mut Self {
-        unsafe {
+        self.timed("controlled_compact_unitary", |reg| unsafe {
             ffi::controlledCompactUnitary(
-                self.reg,
+                reg,
                 control_qubit,
                 target_qubit,
                 alpha.into(),
                 beta.into(),
             );
-        }
-        self
+        })
     }
 
     pub fn two_qubit_unitary(
@@ -446,15 +1693,34 @@ impl<'a> QuReg<'a> {
         target_qubit_two: i32,
         unitary_matrix: ComplexMatrix4,
     ) -> &mut Self {
-        unsafe {
+        self.timed("two_qubit_unitary", |reg| unsafe {
             ffi::twoQubitUnitary(
-                self.reg,
+                reg,
                 target_qubit_one,
                 target_qubit_two,
                 unitary_matrix.into(),
             );
+        })
+    }
+
+    /// Apply a two-qubit unitary whose indices are ordered as `ordering`
+    /// says, e.g. so that a textbook CNOT with its control as the high bit
+    /// can be applied with `QubitOrdering::BigEndian`.
+    pub fn two_qubit_unitary_with_order(
+        &mut self,
+        qubit_one: i32,
+        qubit_two: i32,
+        unitary_matrix: ComplexMatrix4,
+        ordering: QubitOrdering,
+    ) -> &mut Self {
+        match ordering {
+            QubitOrdering::LittleEndian => {
+                self.two_qubit_unitary(qubit_one, qubit_two, unitary_matrix)
+            }
+            QubitOrdering::BigEndian => {
+                self.two_qubit_unitary(qubit_two, qubit_one, unitary_matrix)
+            }
         }
-        self
     }
 
     pub fn controlled_two_qubit_unitary(
@@ -464,16 +1730,15 @@ impl<'a> QuReg<'a> {
         target_qubit_two: i32,
         unitary_matrix: ComplexMatrix4,
     ) -> &mut Self {
-        unsafe {
+        self.timed("controlled_two_qubit_unitary", |reg| unsafe {
             ffi::controlledTwoQubitUnitary(
-                self.reg,
+                reg,
                 control_qubit,
                 target_qubit_one,
                 target_qubit_two,
                 unitary_matrix.into(),
             );
-        }
-        self
+        })
     }
 
     pub fn multi_controlled_two_qubit_unitary(
@@ -483,17 +1748,34 @@ impl<'a> QuReg<'a> {
         target_qubit_two: i32,
         unitary_matrix: ComplexMatrix4,
     ) -> &mut Self {
-        unsafe {
+        self.timed("multi_controlled_two_qubit_unitary", |reg| unsafe {
             ffi::multiControlledTwoQubitUnitary(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
                 target_qubit_one,
                 target_qubit_two,
                 unitary_matrix.into(),
             );
-        }
-        self
+        })
+    }
+
+    /// Like `multi_controlled_two_qubit_unitary`, but returns an error instead
+    /// of aborting if either target is also one of the control qubits.
+    pub fn try_multi_controlled_two_qubit_unitary(
+        &mut self,
+        control_qubits: Vec<i32>,
+        target_qubit_one: i32,
+        target_qubit_two: i32,
+        unitary_matrix: ComplexMatrix4,
+    ) -> Result<&mut Self, QuestError> {
+        check_controls_disjoint(&control_qubits, &[target_qubit_one, target_qubit_two])?;
+        Ok(self.multi_controlled_two_qubit_unitary(
+            control_qubits,
+            target_qubit_one,
+            target_qubit_two,
+            unitary_matrix,
+        ))
     }
 
     pub fn multi_qubit_unitary(
@@ -501,15 +1783,27 @@ impl<'a> QuReg<'a> {
         target_qubits: Vec<i32>,
         unitary_matrix: ComplexMatrixN,
     ) -> &mut Self {
-        unsafe {
+        assert_matrix_size(&unitary_matrix, target_qubits.len());
+
+        self.timed("multi_qubit_unitary", |reg| unsafe {
             ffi::multiQubitUnitary(
-                self.reg,
+                reg,
                 target_qubits.as_ptr() as *mut i32,
                 target_qubits.len() as i32,
                 unitary_matrix.matrix,
             );
-        }
-        self
+        })
+    }
+
+    /// Like `multi_qubit_unitary`, but returns an error instead of panicking
+    /// if the matrix doesn't match the number of target qubits.
+    pub fn try_multi_qubit_unitary(
+        &mut self,
+        target_qubits: Vec<i32>,
+        unitary_matrix: ComplexMatrixN,
+    ) -> Result<&mut Self, QuestError> {
+        check_matrix_size(&unitary_matrix, target_qubits.len())?;
+        Ok(self.multi_qubit_unitary(target_qubits, unitary_matrix))
     }
 
     pub fn controlled_multi_qubit_unitary(
@@ -518,16 +1812,31 @@ impl<'a> QuReg<'a> {
         target_qubits: Vec<i32>,
         unitary_matrix: ComplexMatrixN,
     ) -> &mut Self {
-        unsafe {
+        assert_matrix_size(&unitary_matrix, target_qubits.len());
+
+        self.timed("controlled_multi_qubit_unitary", |reg| unsafe {
             ffi::controlledMultiQubitUnitary(
-                self.reg,
+                reg,
                 control_qubit,
                 target_qubits.as_ptr() as *mut i32,
                 target_qubits.len() as i32,
                 unitary_matrix.matrix,
             );
-        }
-        self
+        })
+    }
+
+    /// Like `controlled_multi_qubit_unitary`, but returns an error instead of
+    /// aborting if the control is also a target, or panicking if the matrix
+    /// doesn't match the number of target qubits.
+    pub fn try_controlled_multi_qubit_unitary(
+        &mut self,
+        control_qubit: i32,
+        target_qubits: Vec<i32>,
+        unitary_matrix: ComplexMatrixN,
+    ) -> Result<&mut Self, QuestError> {
+        check_controls_disjoint(&[control_qubit], &target_qubits)?;
+        check_matrix_size(&unitary_matrix, target_qubits.len())?;
+        Ok(self.controlled_multi_qubit_unitary(control_qubit, target_qubits, unitary_matrix))
     }
 
     pub fn multi_controlled_multi_qubit_unitary(
@@ -536,38 +1845,55 @@ impl<'a> QuReg<'a> {
         target_qubits: Vec<i32>,
         unitary_matrix: &ComplexMatrixN,
     ) -> &mut Self {
-        unsafe {
+        assert_matrix_size(unitary_matrix, target_qubits.len());
+
+        self.timed("multi_controlled_multi_qubit_unitary", |reg| unsafe {
             ffi::multiControlledMultiQubitUnitary(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
                 target_qubits.as_ptr() as *mut i32,
                 target_qubits.len() as i32,
                 unitary_matrix.matrix,
             );
-        }
-        self
+        })
+    }
+
+    /// Like `multi_controlled_multi_qubit_unitary`, but returns an error
+    /// instead of aborting if any target is also one of the control qubits.
+    pub fn try_multi_controlled_multi_qubit_unitary(
+        &mut self,
+        control_qubits: Vec<i32>,
+        target_qubits: Vec<i32>,
+        unitary_matrix: &ComplexMatrixN,
+    ) -> Result<&mut Self, QuestError> {
+        check_controls_disjoint(&control_qubits, &target_qubits)?;
+        check_matrix_size(unitary_matrix, target_qubits.len())?;
+        Ok(
+            self.multi_controlled_multi_qubit_unitary(
+                control_qubits,
+                target_qubits,
+                unitary_matrix,
+            ),
+        )
     }
 
     pub fn rotate_x(&mut self, qubit_to_rotate: i32, angle: QReal) -> &mut Self {
-        unsafe {
-            ffi::rotateX(self.reg, qubit_to_rotate, angle);
-        }
-        self
+        self.timed("rotate_x", |reg| unsafe {
+            ffi::rotateX(reg, qubit_to_rotate, angle);
+        })
     }
 
     pub fn rotate_y(&mut self, qubit_to_rotate: i32, angle: QReal) -> &mut Self {
-        unsafe {
-            ffi::rotateY(self.reg, qubit_to_rotate, angle);
-        }
-        self
+        self.timed("rotate_y", |reg| unsafe {
+            ffi::rotateY(reg, qubit_to_rotate, angle);
+        })
     }
 
     pub fn rotate_z(&mut self, qubit_to_rotate: i32, angle: QReal) -> &mut Self {
-        unsafe {
-            ffi::rotateZ(self.reg, qubit_to_rotate, angle);
-        }
-        self
+        self.timed("rotate_z", |reg| unsafe {
+            ffi::rotateZ(reg, qubit_to_rotate, angle);
+        })
     }
 
     pub fn rotate_around_axis(
@@ -576,10 +1902,9 @@ impl<'a> QuReg<'a> {
         angle: QReal,
         axis: Vector,
     ) -> &mut Self {
-        unsafe {
-            ffi::rotateAroundAxis(self.reg, qubit_to_rotate, angle, axis.into());
-        }
-        self
+        self.timed("rotate_around_axis", |reg| unsafe {
+            ffi::rotateAroundAxis(reg, qubit_to_rotate, angle, axis.into());
+        })
     }
 
     pub fn controlled_rotate_x(
@@ -588,10 +1913,9 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         angle: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledRotateX(self.reg, control_qubit, target_qubit, angle);
-        }
-        self
+        self.timed("controlled_rotate_x", |reg| unsafe {
+            ffi::controlledRotateX(reg, control_qubit, target_qubit, angle);
+        })
     }
 
     pub fn controlled_rotate_y(
@@ -600,10 +1924,9 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         angle: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledRotateY(self.reg, control_qubit, target_qubit, angle);
-        }
-        self
+        self.timed("controlled_rotate_y", |reg| unsafe {
+            ffi::controlledRotateY(reg, control_qubit, target_qubit, angle);
+        })
     }
 
     pub fn controlled_rotate_z(
@@ -612,10 +1935,9 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         angle: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledRotateZ(self.reg, control_qubit, target_qubit, angle);
-        }
-        self
+        self.timed("controlled_rotate_z", |reg| unsafe {
+            ffi::controlledRotateZ(reg, control_qubit, target_qubit, angle);
+        })
     }
 
     pub fn controlled_rotate_around_axis(
@@ -625,60 +1947,254 @@ impl<'a> QuReg<'a> {
         angle: QReal,
         axis: Vector,
     ) -> &mut Self {
-        unsafe {
-            ffi::controlledRotateAroundAxis(
-                self.reg,
-                control_qubit,
-                target_qubit,
-                angle,
-                axis.into(),
-            );
+        self.timed("controlled_rotate_around_axis", |reg| unsafe {
+            ffi::controlledRotateAroundAxis(reg, control_qubit, target_qubit, angle, axis.into());
+        })
+    }
+
+    pub fn pauli_x(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("pauli_x", |reg| unsafe {
+            ffi::pauliX(reg, target_qubit);
+        })
+    }
+
+    pub fn pauli_y(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("pauli_y", |reg| unsafe {
+            ffi::pauliY(reg, target_qubit);
+        })
+    }
+
+    pub fn pauli_z(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("pauli_z", |reg| unsafe {
+            ffi::pauliZ(reg, target_qubit);
+        })
+    }
+
+    pub fn hadamard(&mut self, target_qubit: i32) -> &mut Self {
+        self.timed("hadamard", |reg| unsafe {
+            ffi::hadamard(reg, target_qubit);
+        })
+    }
+
+    pub fn controlled_not(&mut self, control_qubit: i32, target_qubit: i32) -> &mut Self {
+        self.timed("controlled_not", |reg| unsafe {
+            ffi::controlledNot(reg, control_qubit, target_qubit);
+        })
+    }
+
+    pub fn controlled_pauli_y(&mut self, control_qubit: i32, target_qubit: i32) -> &mut Self {
+        self.timed("controlled_pauli_y", |reg| unsafe {
+            ffi::controlledPauliY(reg, control_qubit, target_qubit);
+        })
+    }
+
+    /// Replace every amplitude (or density-matrix element) with its complex
+    /// conjugate.
+    pub fn conjugate(&mut self) -> &mut Self {
+        self.map_local_amplitudes(|_, amplitude| Complex::new(amplitude.real, -amplitude.imag));
+        self
+    }
+
+    /// Apply Grover's diffusion operator `2|s><s| - I` to `qubits`, where
+    /// `|s>` is their uniform superposition. On a state with real amplitudes,
+    /// this reflects each amplitude about their mean.
+    ///
+    /// The reflection is built from Hadamard and X gates around a
+    /// multi-controlled Z, which flips the phase of `|0...0>`. That gives
+    /// `I - 2|s><s|`, which only differs by an unobservable global phase.
+    pub fn apply_grover_diffuser(&mut self, qubits: &[i32]) -> &mut Self {
+        if qubits.is_empty() {
+            panic!("Grover's diffuser needs at least one qubit.");
+        }
+
+        for &qubit in qubits {
+            self.hadamard(qubit);
+        }
+        self.flip_zero_state_phase(qubits);
+        for &qubit in qubits {
+            self.hadamard(qubit);
         }
         self
     }
 
-    pub fn pauli_x(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::pauliX(self.reg, target_qubit);
+    /// Apply `S_0 = I - 2|0...0><0...0|` to `qubits`, with X gates around a
+    /// multi-controlled Z.
+    pub(crate) fn flip_zero_state_phase(&mut self, qubits: &[i32]) -> &mut Self {
+        for &qubit in qubits {
+            self.pauli_x(qubit);
+        }
+        self.multi_controlled_z(qubits);
+        for &qubit in qubits {
+            self.pauli_x(qubit);
         }
         self
     }
 
-    pub fn pauli_y(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::pauliY(self.reg, target_qubit);
+    /// Apply the quantum Fourier transform to `qubits`, where `qubits[0]` is
+    /// the least significant bit, so that `|x>` becomes
+    /// `sum_y exp(2πixy/2^n) |y> / sqrt(2^n)`.
+    ///
+    /// QuEST 3.1 has no built-in QFT, so this is the textbook circuit of
+    /// Hadamards and controlled phase shifts, followed by swaps to reverse the
+    /// qubit order.
+    pub fn apply_qft(&mut self, qubits: &[i32]) -> &mut Self {
+        let num_qubits = qubits.len();
+        for j in (0..num_qubits).rev() {
+            let stage: Vec<i32> = qubits[..=j].iter().rev().copied().collect();
+            self.hadamard(qubits[j]).apply_phase_gradient(&stage);
+        }
+        for i in 0..num_qubits / 2 {
+            self.swap_gate(qubits[i], qubits[num_qubits - 1 - i]);
         }
         self
     }
 
-    pub fn pauli_z(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::pauliZ(self.reg, target_qubit);
+    /// Apply the controlled phase rotations of one QFT stage to `qubits[0]`,
+    /// with each `qubits[k]` controlling a phase shift of `π/2^k` (the gate
+    /// usually written `R_{k+1}`).
+    pub fn apply_phase_gradient(&mut self, qubits: &[i32]) -> &mut Self {
+        for (k, &control_qubit) in qubits.iter().enumerate().skip(1) {
+            let angle = PI / (1u64 << k) as f64;
+            self.controlled_phase_shift(control_qubit, qubits[0], angle as QReal);
         }
         self
     }
 
-    pub fn hadamard(&mut self, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::hadamard(self.reg, target_qubit);
+    /// Apply the inverse of `apply_qft`, running its gates in reverse with
+    /// conjugated phases.
+    pub fn apply_inverse_qft(&mut self, qubits: &[i32]) -> &mut Self {
+        let num_qubits = qubits.len();
+        for i in 0..num_qubits / 2 {
+            self.swap_gate(qubits[i], qubits[num_qubits - 1 - i]);
+        }
+        for j in 0..num_qubits {
+            for k in 0..j {
+                let angle = -PI / (1u64 << (j - k)) as f64;
+                self.controlled_phase_shift(qubits[k], qubits[j], angle as QReal);
+            }
+            self.hadamard(qubits[j]);
         }
         self
     }
 
-    pub fn controlled_not(&mut self, control_qubit: i32, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::controlledNot(self.reg, control_qubit, target_qubit);
+    /// Call `f` on this register `times` times in a row, e.g. to apply `U^k`
+    /// for a gate sequence `U`.
+    pub fn apply_repeated<F>(&mut self, times: u32, mut f: F) -> &mut Self
+    where
+        F: FnMut(&mut Self),
+    {
+        for _ in 0..times {
+            f(self);
         }
         self
     }
 
-    pub fn controlled_pauli_y(&mut self, control_qubit: i32, target_qubit: i32) -> &mut Self {
-        unsafe {
-            ffi::controlledPauliY(self.reg, control_qubit, target_qubit);
+    /// Call `step` until `converged` holds or `max_iters` steps have run,
+    /// returning how many ran. Convergence is checked before every step, so
+    /// a register that has already converged is left alone.
+    pub fn iterate_until<F, C>(&mut self, mut step: F, converged: C, max_iters: usize) -> usize
+    where
+        F: FnMut(&mut Self),
+        C: Fn(&Self) -> bool,
+    {
+        let mut iterations = 0;
+        while iterations < max_iters && !converged(self) {
+            step(self);
+            iterations += 1;
+        }
+        iterations
+    }
+
+    /// Apply `layers` layers of a hardware-efficient ansatz. Each layer
+    /// rotates every qubit `q` by `rotate_y` then `rotate_z`, with angles
+    /// `params[2 * (layer * n + q)]` and `params[2 * (layer * n + q) + 1]` on
+    /// an `n`-qubit register, and then entangles the qubits with CNOTs in the
+    /// given pattern.
+    pub fn apply_hardware_efficient_ansatz(
+        &mut self,
+        layers: usize,
+        params: &[QReal],
+        entangler: EntanglerPattern,
+    ) -> &mut Self {
+        let num_qubits = self.num_qubits();
+        let expected = 2 * layers * num_qubits as usize;
+        if params.len() != expected {
+            panic!(
+                "Got {} parameters, but {} layers on {} qubits need {}.",
+                params.len(),
+                layers,
+                num_qubits,
+                expected
+            );
+        }
+
+        for layer in params.chunks(2 * num_qubits as usize) {
+            for (qubit, angles) in (0..num_qubits).zip(layer.chunks(2)) {
+                self.rotate_y(qubit, angles[0]).rotate_z(qubit, angles[1]);
+            }
+            match entangler {
+                EntanglerPattern::Linear => {
+                    for qubit in 1..num_qubits {
+                        self.controlled_not(qubit - 1, qubit);
+                    }
+                }
+                EntanglerPattern::AllToAll => {
+                    for control in 0..num_qubits {
+                        for target in control + 1..num_qubits {
+                            self.controlled_not(control, target);
+                        }
+                    }
+                }
+            }
+        }
+        self
+    }
+
+    /// Call `f` with each qubit of this register in turn, e.g. to apply a
+    /// single-qubit gate to every qubit.
+    pub fn apply_to_all<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&mut Self, i32),
+    {
+        for qubit in 0..self.num_qubits() {
+            f(self, qubit);
         }
         self
     }
 
+    /// Call `gate` with each qubit of this register for which `predicate`
+    /// holds, e.g. to flip the qubits that are 0 in a marked basis state
+    /// before a multi-controlled Z.
+    pub fn apply_where<P, F>(&mut self, predicate: P, gate: F) -> &mut Self
+    where
+        P: Fn(i32) -> bool,
+        F: Fn(&mut Self, i32),
+    {
+        self.apply_to_all(|qureg, qubit| {
+            if predicate(qubit) {
+                gate(qureg, qubit);
+            }
+        })
+    }
+
+    pub fn hadamard_all(&mut self) -> &mut Self {
+        self.apply_to_all(|qureg, qubit| {
+            qureg.hadamard(qubit);
+        })
+    }
+
+    pub fn pauli_x_all(&mut self) -> &mut Self {
+        self.apply_to_all(|qureg, qubit| {
+            qureg.pauli_x(qubit);
+        })
+    }
+
+    /// Apply the following gates conditioned on `control_qubit`, using the
+    /// controlled version of each gate.
+    pub fn with_control(&mut self, control_qubit: i32) -> ControlledQuReg<'_, 'a> {
+        ControlledQuReg::new(self, control_qubit)
+    }
+
     pub fn inner_product(&self, ket: QuReg) -> Complex {
         unsafe { ffi::calcInnerProduct(self.reg, ket.reg).into() }
     }
@@ -724,10 +2240,10 @@ impl<'a> QuReg<'a> {
     }
 
     pub fn mix_dephasing(&mut self, target_qubit: i32, probability: QReal) -> &mut Self {
-        unsafe {
-            ffi::mixDephasing(self.reg, target_qubit, probability);
-        }
-        self
+        self.assert_density();
+        self.timed("mix_dephasing", |reg| unsafe {
+            ffi::mixDephasing(reg, target_qubit, probability);
+        })
     }
 
     pub fn mix_two_qubit_dephasing(
@@ -736,17 +2252,17 @@ impl<'a> QuReg<'a> {
         qubit_two: i32,
         probability: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::mixTwoQubitDephasing(self.reg, qubit_one, qubit_two, probability);
-        }
-        self
+        self.assert_density();
+        self.timed("mix_two_qubit_dephasing", |reg| unsafe {
+            ffi::mixTwoQubitDephasing(reg, qubit_one, qubit_two, probability);
+        })
     }
 
     pub fn mix_depolarising(&mut self, target_qubit: i32, probability: QReal) -> &mut Self {
-        unsafe {
-            ffi::mixDepolarising(self.reg, target_qubit, probability);
-        }
-        self
+        self.assert_density();
+        self.timed("mix_depolarising", |reg| unsafe {
+            ffi::mixDepolarising(reg, target_qubit, probability);
+        })
     }
 
     pub fn mix_two_qubit_depolarising(
@@ -755,17 +2271,17 @@ impl<'a> QuReg<'a> {
         qubit_two: i32,
         probability: QReal,
     ) -> &mut Self {
-        unsafe {
-            ffi::mixTwoQubitDepolarising(self.reg, qubit_one, qubit_two, probability);
-        }
-        self
+        self.assert_density();
+        self.timed("mix_two_qubit_depolarising", |reg| unsafe {
+            ffi::mixTwoQubitDepolarising(reg, qubit_one, qubit_two, probability);
+        })
     }
 
     pub fn mix_damping(&mut self, target_qubit: i32, probability: QReal) -> &mut Self {
-        unsafe {
-            ffi::mixDamping(self.reg, target_qubit, probability);
-        }
-        self
+        self.assert_density();
+        self.timed("mix_damping", |reg| unsafe {
+            ffi::mixDamping(reg, target_qubit, probability);
+        })
     }
 
     pub fn mix_pauli(
@@ -775,23 +2291,24 @@ impl<'a> QuReg<'a> {
         probability_y: QReal,
         probability_z: QReal,
     ) -> &mut Self {
-        unsafe {
+        self.assert_density();
+        self.timed("mix_pauli", |reg| unsafe {
             ffi::mixPauli(
-                self.reg,
+                reg,
                 target_qubit,
                 probability_x,
                 probability_y,
                 probability_z,
             );
-        }
-        self
+        })
     }
 
     pub fn mix_density_matrix(&mut self, probability: QReal, other_qureg: QuReg) -> &mut Self {
-        unsafe {
-            ffi::mixDensityMatrix(self.reg, probability, other_qureg.reg);
-        }
-        self
+        self.assert_density();
+        other_qureg.assert_density();
+        self.timed("mix_density_matrix", |reg| unsafe {
+            ffi::mixDensityMatrix(reg, probability, other_qureg.reg);
+        })
     }
 
     pub fn mix_kraus_map(
@@ -799,18 +2316,18 @@ impl<'a> QuReg<'a> {
         target_qubit: i32,
         kraus_operators: Vec<ComplexMatrix2>,
     ) -> &mut Self {
+        self.assert_density();
         let num_operators = kraus_operators.len();
         let ffi_kraus_operators: Vec<ffi::ComplexMatrix2> =
             kraus_operators.into_iter().map(Into::into).collect();
-        unsafe {
+        self.timed("mix_kraus_map", |reg| unsafe {
             ffi::mixKrausMap(
-                self.reg,
+                reg,
                 target_qubit,
                 ffi_kraus_operators.as_ptr() as *mut ffi::ComplexMatrix2,
                 num_operators as i32,
             );
-        }
-        self
+        })
     }
 
     pub fn mix_two_qubit_kraus_map(
@@ -819,19 +2336,19 @@ impl<'a> QuReg<'a> {
         target_qubit_two: i32,
         kraus_operators: Vec<ComplexMatrix4>,
     ) -> &mut Self {
+        self.assert_density();
         let num_operators = kraus_operators.len();
         let ffi_kraus_operators: Vec<ffi::ComplexMatrix4> =
             kraus_operators.into_iter().map(Into::into).collect();
-        unsafe {
+        self.timed("mix_two_qubit_kraus_map", |reg| unsafe {
             ffi::mixTwoQubitKrausMap(
-                self.reg,
+                reg,
                 target_qubit_one,
                 target_qubit_two,
                 ffi_kraus_operators.as_ptr() as *mut ffi::ComplexMatrix4,
                 num_operators as i32,
             );
-        }
-        self
+        })
     }
 
     pub fn mix_multi_qubit_kraus_map(
@@ -839,35 +2356,216 @@ impl<'a> QuReg<'a> {
         target_qubits: Vec<i32>,
         kraus_operators: Vec<ComplexMatrixN>,
     ) -> &mut Self {
+        self.assert_density();
         let num_operators = kraus_operators.len();
         let kraus_ops_native = kraus_operators
             .iter()
             .map(|op| op.matrix)
             .collect::<Vec<ffi::ComplexMatrixN>>();
-        unsafe {
+        self.timed("mix_multi_qubit_kraus_map", |reg| unsafe {
             ffi::mixMultiQubitKrausMap(
-                self.reg,
+                reg,
                 target_qubits.as_ptr() as *mut i32,
                 target_qubits.len() as i32,
                 kraus_ops_native.as_ptr() as *mut ffi::ComplexMatrixN,
                 num_operators as i32,
             );
+        })
+    }
+
+    /// Apply the decoherence each qubit undergoes during a gate lasting
+    /// `gate_time`, given its relaxation time `t1[qubit]` and dephasing time
+    /// `t2[qubit]` in the same units. Use `QReal::INFINITY` for a qubit that
+    /// doesn't decohere.
+    ///
+    /// Relaxation is amplitude damping with probability `1 - exp(-t/T1)`.
+    /// Dephasing makes up the rest of the `exp(-t/T2)` decay of coherences,
+    /// which requires `T2 <= 2 T1`.
+    pub fn apply_t1_t2_noise(&mut self, t1: &[QReal], t2: &[QReal], gate_time: QReal) -> &mut Self {
+        let num_qubits = self.num_qubits() as usize;
+        if t1.len() != num_qubits || t2.len() != num_qubits {
+            panic!(
+                "Need one T1 and T2 time per qubit, but got {} and {} for {} qubits.",
+                t1.len(),
+                t2.len(),
+                num_qubits
+            );
+        }
+
+        for (qubit, (&t1, &t2)) in t1.iter().zip(t2).enumerate() {
+            if t1 <= 0.0 || t2 <= 0.0 || t1.is_nan() || t2.is_nan() {
+                panic!("T1 and T2 must be positive, not {} and {}.", t1, t2);
+            }
+            if t2 > 2.0 * t1 {
+                panic!(
+                    "T2 of {} is more than twice T1 of {} on qubit {}.",
+                    t2, t1, qubit
+                );
+            }
+
+            let damping = 1.0 - (-gate_time / t1).exp();
+            let dephasing_rate = (1.0 / t2 - 0.5 / t1).max(0.0);
+            let dephasing = (1.0 - (-gate_time * dephasing_rate).exp()) / 2.0;
+            self.mix_damping(qubit as i32, damping)
+                .mix_dephasing(qubit as i32, dephasing);
         }
         self
     }
 
-    pub fn swap_gate(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
-        unsafe {
-            ffi::swapGate(self.reg, qubit_one, qubit_two);
+    // Like the `mix_*` methods, the `try_mix_*` methods apply a noise channel,
+    // but return an error instead of panicking if the register isn't a
+    // density matrix.
+    pub fn try_mix_dephasing(
+        &mut self,
+        target_qubit: i32,
+        probability: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_dephasing(target_qubit, probability))
+    }
+
+    pub fn try_mix_two_qubit_dephasing(
+        &mut self,
+        qubit_one: i32,
+        qubit_two: i32,
+        probability: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_two_qubit_dephasing(qubit_one, qubit_two, probability))
+    }
+
+    pub fn try_mix_depolarising(
+        &mut self,
+        target_qubit: i32,
+        probability: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_depolarising(target_qubit, probability))
+    }
+
+    pub fn try_mix_two_qubit_depolarising(
+        &mut self,
+        qubit_one: i32,
+        qubit_two: i32,
+        probability: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_two_qubit_depolarising(qubit_one, qubit_two, probability))
+    }
+
+    pub fn try_mix_damping(
+        &mut self,
+        target_qubit: i32,
+        probability: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_damping(target_qubit, probability))
+    }
+
+    pub fn try_mix_pauli(
+        &mut self,
+        target_qubit: i32,
+        probability_x: QReal,
+        probability_y: QReal,
+        probability_z: QReal,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_pauli(target_qubit, probability_x, probability_y, probability_z))
+    }
+
+    pub fn try_mix_density_matrix(
+        &mut self,
+        probability: QReal,
+        other_qureg: QuReg,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        other_qureg.ensure_density()?;
+        Ok(self.mix_density_matrix(probability, other_qureg))
+    }
+
+    pub fn try_mix_kraus_map(
+        &mut self,
+        target_qubit: i32,
+        kraus_operators: Vec<ComplexMatrix2>,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_kraus_map(target_qubit, kraus_operators))
+    }
+
+    pub fn try_mix_two_qubit_kraus_map(
+        &mut self,
+        target_qubit_one: i32,
+        target_qubit_two: i32,
+        kraus_operators: Vec<ComplexMatrix4>,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_two_qubit_kraus_map(target_qubit_one, target_qubit_two, kraus_operators))
+    }
+
+    pub fn try_mix_multi_qubit_kraus_map(
+        &mut self,
+        target_qubits: Vec<i32>,
+        kraus_operators: Vec<ComplexMatrixN>,
+    ) -> Result<&mut Self, QuestError> {
+        self.ensure_density()?;
+        Ok(self.mix_multi_qubit_kraus_map(target_qubits, kraus_operators))
+    }
+
+    fn ensure_density(&self) -> Result<(), QuestError> {
+        if !self.is_density_matrix() {
+            return Err(QuestError::RequiresDensityMatrix);
         }
-        self
+        Ok(())
     }
 
-    pub fn sqrt_swap_gate(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
-        unsafe {
-            ffi::sqrtSwapGate(self.reg, qubit_one, qubit_two);
+    fn assert_density(&self) {
+        if let Err(error) = self.ensure_density() {
+            panic!("{}.", error);
         }
-        self
+    }
+
+    pub fn swap_gate(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
+        self.timed("swap_gate", |reg| unsafe {
+            ffi::swapGate(reg, qubit_one, qubit_two);
+        })
+    }
+
+    pub fn sqrt_swap_gate(&mut self, qubit_one: i32, qubit_two: i32) -> &mut Self {
+        self.timed("sqrt_swap_gate", |reg| unsafe {
+            ffi::sqrtSwapGate(reg, qubit_one, qubit_two);
+        })
+    }
+
+    /// The Fredkin gate, which swaps `qubit_one` and `qubit_two` if
+    /// `control_qubit` is 1.
+    pub fn controlled_swap(
+        &mut self,
+        control_qubit: i32,
+        qubit_one: i32,
+        qubit_two: i32,
+    ) -> &mut Self {
+        let mut swap = [[0.0; 4]; 4];
+        swap[0][0] = 1.0;
+        swap[1][2] = 1.0;
+        swap[2][1] = 1.0;
+        swap[3][3] = 1.0;
+        self.controlled_two_qubit_unitary(
+            control_qubit,
+            qubit_one,
+            qubit_two,
+            ComplexMatrix4::real(swap),
+        )
+    }
+
+    /// The Toffoli gate, which flips `target_qubit` if both controls are 1.
+    pub fn toffoli(&mut self, control_one: i32, control_two: i32, target_qubit: i32) -> &mut Self {
+        self.multi_controlled_not(&[control_one, control_two], target_qubit)
+    }
+
+    /// Flip `target_qubit` if all of `control_qubits` are 1.
+    pub fn multi_controlled_not(&mut self, control_qubits: &[i32], target_qubit: i32) -> &mut Self {
+        let pauli_x = ComplexMatrix2::real([[0.0, 1.0], [1.0, 0.0]]);
+        self.multi_controlled_unitary(control_qubits.to_vec(), target_qubit, pauli_x)
     }
 
     pub fn multi_state_controlled_unitary(
@@ -883,31 +2581,73 @@ impl<'a> QuReg<'a> {
             );
         }
 
-        unsafe {
+        self.timed("multi_state_controlled_unitary", |reg| unsafe {
             ffi::multiStateControlledUnitary(
-                self.reg,
+                reg,
                 control_qubits.as_ptr() as *mut i32,
                 control_states.as_ptr() as *mut i32,
                 control_qubits.len() as i32,
                 target_qubit,
                 unitary_matrix.into(),
             );
-        }
-        self
+        })
+    }
+
+    /// Like `multi_state_controlled_unitary`, but returns an error instead of
+    /// aborting if the target is also one of the control qubits.
+    pub fn try_multi_state_controlled_unitary(
+        &mut self,
+        control_qubits: Vec<i32>,
+        control_states: Vec<i32>,
+        target_qubit: i32,
+        unitary_matrix: ComplexMatrix2,
+    ) -> Result<&mut Self, QuestError> {
+        check_controls_disjoint(&control_qubits, &[target_qubit])?;
+        Ok(self.multi_state_controlled_unitary(
+            control_qubits,
+            control_states,
+            target_qubit,
+            unitary_matrix,
+        ))
     }
 
     pub fn multi_rotate_z(&mut self, target_qubits: Vec<i32>, angle: QReal) -> &mut Self {
-        unsafe {
+        self.timed("multi_rotate_z", |reg| unsafe {
             ffi::multiRotateZ(
-                self.reg,
+                reg,
                 target_qubits.as_ptr() as *mut i32,
                 target_qubits.len() as i32,
                 angle,
             );
+        })
+    }
+
+    /// Rotate each of `qubits` about the Z axis by its own angle in `angles`.
+    /// Unlike `multi_rotate_z`, which applies `exp(-i θ/2 Z⊗Z⊗...)`, this is a
+    /// product of independent single-qubit rotations.
+    pub fn multi_rotate_z_weighted(&mut self, qubits: &[i32], angles: &[QReal]) -> &mut Self {
+        if qubits.len() != angles.len() {
+            panic!(
+                "Got {} angles for {} qubits, but need one angle per qubit.",
+                angles.len(),
+                qubits.len()
+            );
+        }
+
+        for (&qubit, &angle) in qubits.iter().zip(angles) {
+            self.rotate_z(qubit, angle);
         }
         self
     }
 
+    /// Apply the Ising ZZ coupling `exp(-i angle/2 Z⊗Z)` to two qubits, built
+    /// from a `rotate_z` of the target between two CNOTs.
+    pub fn ising_coupling(&mut self, qubit_one: i32, qubit_two: i32, angle: QReal) -> &mut Self {
+        self.controlled_not(qubit_one, qubit_two)
+            .rotate_z(qubit_two, angle)
+            .controlled_not(qubit_one, qubit_two)
+    }
+
     pub fn multi_rotate_pauli(
         &mut self,
         target_qubits: Vec<i32>,
@@ -921,15 +2661,64 @@ impl<'a> QuReg<'a> {
         let ffi_target_paulis: Vec<ffi::pauliOpType> =
             target_paulis.into_iter().map(Into::into).collect();
 
-        unsafe {
+        self.timed("multi_rotate_pauli", |reg| unsafe {
             ffi::multiRotatePauli(
-                self.reg,
+                reg,
                 target_qubits.as_ptr() as *mut i32,
                 ffi_target_paulis.as_ptr() as *mut u32,
                 target_qubits.len() as i32,
                 angle,
             );
+        })
+    }
+
+    /// Apply the Pauli gadget `exp(-i angle/2 P)` for the Pauli product `P`
+    /// of `paulis` on `qubits`.
+    ///
+    /// This is the same operation as `multi_rotate_pauli`, but built from
+    /// gates that every QuEST version has: each X or Y factor is rotated into
+    /// the Z basis, `multi_rotate_z` is applied to the non-identity qubits
+    /// and the basis change is then undone.
+    pub fn apply_pauli_gadget(
+        &mut self,
+        qubits: &[i32],
+        paulis: &[PauliOpType],
+        angle: QReal,
+    ) -> &mut Self {
+        if qubits.len() != paulis.len() {
+            panic!("Number of target qubits must be the same as number of target Pauli operation types");
+        }
+
+        let half_pi = std::f64::consts::FRAC_PI_2 as QReal;
+        let change_basis = |qureg: &mut Self, undo: bool| {
+            for (&qubit, pauli) in qubits.iter().zip(paulis) {
+                match pauli {
+                    PauliOpType::PauliX => {
+                        qureg.hadamard(qubit);
+                    }
+                    // Rx(-π/2) Z Rx(π/2) = Y.
+                    PauliOpType::PauliY => {
+                        qureg.rotate_x(qubit, if undo { -half_pi } else { half_pi });
+                    }
+                    PauliOpType::PauliI | PauliOpType::PauliZ => {}
+                }
+            }
+        };
+
+        let rotated_qubits: Vec<i32> = qubits
+            .iter()
+            .zip(paulis)
+            .filter(|(_, pauli)| **pauli != PauliOpType::PauliI)
+            .map(|(&qubit, _)| qubit)
+            .collect();
+        if rotated_qubits.is_empty() {
+            // Only a global phase.
+            return self;
         }
+
+        change_basis(self, false);
+        self.multi_rotate_z(rotated_qubits, angle);
+        change_basis(self, true);
         self
     }
 
@@ -965,12 +2754,326 @@ impl<'a> QuReg<'a> {
     }
 }
 
+fn check_controls_disjoint(
+    control_qubits: &[i32],
+    target_qubits: &[i32],
+) -> Result<(), QuestError> {
+    match target_qubits
+        .iter()
+        .find(|target| control_qubits.contains(target))
+    {
+        Some(&qubit) => Err(QuestError::ControlTargetOverlap { qubit }),
+        None => Ok(()),
+    }
+}
+
+fn check_targets(target_qubits: &[i32], num_qubits: i32) -> Result<(), QuestError> {
+    for (i, &qubit) in target_qubits.iter().enumerate() {
+        if qubit < 0 || qubit >= num_qubits {
+            return Err(QuestError::QubitOutOfRange { qubit, num_qubits });
+        }
+        if target_qubits[..i].contains(&qubit) {
+            return Err(QuestError::DuplicateQubit { qubit });
+        }
+    }
+    Ok(())
+}
+
+fn assert_targets(target_qubits: &[i32], num_qubits: i32) {
+    if let Err(error) = check_targets(target_qubits, num_qubits) {
+        panic!("{}.", error);
+    }
+}
+
+fn check_matrix_size(matrix: &ComplexMatrixN, num_target_qubits: usize) -> Result<(), QuestError> {
+    if 1usize.checked_shl(num_target_qubits as u32) != Some(matrix.dim()) {
+        return Err(QuestError::MatrixSizeMismatch {
+            matrix_dim: matrix.dim(),
+            num_target_qubits,
+        });
+    }
+    Ok(())
+}
+
+fn assert_matrix_size(matrix: &ComplexMatrixN, num_target_qubits: usize) {
+    if let Err(error) = check_matrix_size(matrix, num_target_qubits) {
+        panic!("{}.", error);
+    }
+}
+
+/// Compute the matrix of inner products `<states[i]|states[j]>` between
+/// state vectors, as used in subspace-expansion methods.
+///
+/// The matrix is Hermitian, so only the upper triangle is calculated and the
+/// rest is filled in by conjugation.
+pub fn overlap_matrix(states: &[&QuReg]) -> Vec<Vec<Complex>> {
+    let num_states = states.len();
+    let mut overlaps = vec![vec![Complex::zero(); num_states]; num_states];
+    for i in 0..num_states {
+        for j in i..num_states {
+            let overlap: Complex =
+                unsafe { ffi::calcInnerProduct(states[i].reg, states[j].reg) }.into();
+            overlaps[i][j] = overlap;
+            overlaps[j][i] = Complex::new(overlap.real, -overlap.imag);
+        }
+    }
+    overlaps
+}
+
+/// Check whether two registers hold the same state to within `tolerance` on
+/// every amplitude, ignoring any global phase between state vectors.
+///
+/// Registers of different sizes, or a state vector and a density matrix, are
+/// never approximately equal.
+pub fn qureg_approx_eq(a: &QuReg, b: &QuReg, tolerance: QReal) -> bool {
+    match largest_deviation(a, b) {
+        Some(deviation) => deviation.distance <= tolerance,
+        None => false,
+    }
+}
+
+/// Assert that two registers hold the same state, as in `qureg_approx_eq`.
+///
+/// On failure, the panic message shows the amplitude that deviates the most.
+///
+/// ## Examples
+/// ```
+/// use quest_rs::{assert_qureg_close, QuestEnv, QuReg, DEFAULT_TOLERANCE};
+///
+/// let env = QuestEnv::new();
+/// let mut a = QuReg::new(1, &env);
+/// let mut b = QuReg::new(1, &env);
+/// a.init_classical_state(1);
+/// // Y|0> = i|1>, which only differs from |1> by a global phase.
+/// b.init_zero_state().pauli_y(0);
+/// assert_qureg_close(&a, &b, DEFAULT_TOLERANCE);
+/// ```
+pub fn assert_qureg_close(a: &QuReg, b: &QuReg, tolerance: QReal) {
+    let deviation = match largest_deviation(a, b) {
+        Some(deviation) => deviation,
+        None => panic!(
+            "Can't compare a register of {} qubits (density matrix: {}) with one of {} qubits \
+             (density matrix: {}).",
+            a.reg.numQubitsRepresented,
+            a.is_density_matrix(),
+            b.reg.numQubitsRepresented,
+            b.is_density_matrix()
+        ),
+    };
+    if deviation.distance > tolerance {
+        panic!(
+            "Registers differ by {} (tolerance {}) at amplitude {}: ({}, {}) != ({}, {})",
+            format_real(deviation.distance),
+            format_real(tolerance),
+            deviation.index,
+            format_real(deviation.left.real),
+            format_real(deviation.left.imag),
+            format_real(deviation.right.real),
+            format_real(deviation.right.imag)
+        );
+    }
+}
+
+struct Deviation {
+    index: i64,
+    left: Complex,
+    right: Complex,
+    distance: QReal,
+}
+
+/// Find the amplitude at which `a`, rotated by the global phase that best
+/// aligns it with `b`, differs most from `b`.
+fn largest_deviation(a: &QuReg, b: &QuReg) -> Option<Deviation> {
+    if a.reg.numQubitsRepresented != b.reg.numQubitsRepresented
+        || a.is_density_matrix() != b.is_density_matrix()
+    {
+        return None;
+    }
+
+    let stored_amplitudes = |qureg: &QuReg| -> Vec<Complex> {
+        let dim = 1i64 << qureg.reg.numQubitsRepresented;
+        if qureg.is_density_matrix() {
+            (0..dim * dim)
+                .map(|index| qureg.density_amplitude(index % dim, index / dim))
+                .collect()
+        } else {
+            (0..dim).map(|index| qureg.amplitude(index)).collect()
+        }
+    };
+    let (left, right) = (stored_amplitudes(a), stored_amplitudes(b));
+
+    // The phase of <a|b> rotates a onto b. Density matrices have no global
+    // phase, and nor does a zero overlap.
+    let mut phase = Complex::real(1.0);
+    if !a.is_density_matrix() {
+        let overlap = left
+            .iter()
+            .zip(&right)
+            .fold(Complex::zero(), |sum, (x, y)| {
+                Complex::new(
+                    sum.real + x.real * y.real + x.imag * y.imag,
+                    sum.imag + x.real * y.imag - x.imag * y.real,
+                )
+            });
+        let norm = overlap.real.hypot(overlap.imag);
+        if norm > DEFAULT_TOLERANCE {
+            phase = Complex::new(overlap.real / norm, overlap.imag / norm);
+        }
+    }
+
+    left.into_iter()
+        .zip(right)
+        .enumerate()
+        .map(|(index, (x, y))| {
+            let rotated = Complex::new(
+                x.real * phase.real - x.imag * phase.imag,
+                x.real * phase.imag + x.imag * phase.real,
+            );
+            Deviation {
+                index: index as i64,
+                left: rotated,
+                right: y,
+                distance: (rotated.real - y.real).hypot(rotated.imag - y.imag),
+            }
+        })
+        .fold(
+            None,
+            |largest: Option<Deviation>, deviation| match largest {
+                Some(largest) if largest.distance >= deviation.distance => Some(largest),
+                _ => Some(deviation),
+            },
+        )
+}
+
+fn check_allocation(num_qubits: i32, is_density: bool, env: &QuestEnv) -> Result<(), QuestError> {
+    if num_qubits <= 0 {
+        return Err(QuestError::InvalidQubitCount { num_qubits });
+    }
+
+    let required_bytes = estimate_state_vector_bytes(num_qubits, is_density);
+    if required_bytes > isize::MAX as u64 {
+        return Err(QuestError::AllocationTooLarge { required_bytes });
+    }
+    if let Some(limit_bytes) = env.memory_limit() {
+        if required_bytes > limit_bytes {
+            return Err(QuestError::MemoryLimitExceeded {
+                required_bytes,
+                limit_bytes,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Estimate how many bytes QuEST needs to store the amplitudes of a register.
+///
+/// Each amplitude is stored as a real and an imaginary `QReal`. A density
+/// matrix on `num_qubits` qubits is stored as a state vector on twice as many
+/// qubits, so its size is the square of the state-vector size. The estimate
+/// saturates at `u64::MAX` for registers too large to address.
+pub fn estimate_state_vector_bytes(num_qubits: i32, is_density: bool) -> u64 {
+    let num_qubits = num_qubits.max(0) as u32;
+    let num_stored_qubits = if is_density {
+        2 * num_qubits
+    } else {
+        num_qubits
+    };
+    let num_amps = 1u64.checked_shl(num_stored_qubits).unwrap_or(u64::MAX);
+
+    num_amps.saturating_mul(2 * std::mem::size_of::<QReal>() as u64)
+}
+
+/// The memory a register needs when distributed over MPI ranks, from
+/// `memory_report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The amplitudes of the whole register, as in
+    /// `estimate_state_vector_bytes`.
+    pub total_bytes: u64,
+    /// What each rank allocates: its share of the amplitudes, and with more
+    /// than one rank, a buffer of the same size for amplitudes exchanged
+    /// with other ranks.
+    pub bytes_per_rank: u64,
+}
+
+impl MemoryReport {
+    /// Whether every rank fits within `budget_bytes` of memory.
+    pub fn fits_in(&self, budget_bytes: u64) -> bool {
+        self.bytes_per_rank <= budget_bytes
+    }
+}
+
+/// Report how much memory a register of `num_qubits` needs on each of
+/// `num_ranks` MPI ranks, to help choose how many qubits a distributed job
+/// can simulate.
+///
+/// QuEST splits the amplitudes evenly over the ranks, so `num_ranks` must be a
+/// power of two no larger than the number of amplitudes.
+pub fn memory_report(num_qubits: i32, num_ranks: i32, is_density: bool) -> MemoryReport {
+    if num_ranks < 1 || num_ranks & (num_ranks - 1) != 0 {
+        panic!("Number of ranks must be a power of two, not {}.", num_ranks);
+    }
+
+    let total_bytes = estimate_state_vector_bytes(num_qubits, is_density);
+    let amp_bytes = 2 * std::mem::size_of::<QReal>() as u64;
+    if total_bytes / amp_bytes < num_ranks as u64 {
+        panic!(
+            "A register of {} qubits is too small to split over {} ranks.",
+            num_qubits, num_ranks
+        );
+    }
+
+    let bytes_per_rank = total_bytes / num_ranks as u64;
+    MemoryReport {
+        total_bytes,
+        bytes_per_rank: if num_ranks > 1 {
+            bytes_per_rank.saturating_mul(2)
+        } else {
+            bytes_per_rank
+        },
+    }
+}
+
+/// The value of each qubit in the basis state at `index`, where `bits[q]` is
+/// qubit `q`.
+///
+/// QuEST numbers basis states with qubit 0 as the least significant bit, so
+/// index 5 (`0b101`) on 3 qubits is `[1, 0, 1]`, and index 1 is `[1, 0, 0]`.
+pub fn basis_index_to_bits(index: i64, num_qubits: i32) -> Vec<u8> {
+    if !(0..64).contains(&num_qubits) || index < 0 || index >> num_qubits != 0 {
+        panic!(
+            "Basis state {} is out of range for {} qubits.",
+            index, num_qubits
+        );
+    }
+
+    (0..num_qubits)
+        .map(|qubit| (index >> qubit & 1) as u8)
+        .collect()
+}
+
+/// The index of the basis state in which qubit `q` has value `bits[q]`, the
+/// inverse of `basis_index_to_bits`.
+pub fn bits_to_basis_index(bits: &[u8]) -> i64 {
+    if bits.len() > 63 {
+        panic!("{} bits don't fit in a basis state index.", bits.len());
+    }
+
+    bits.iter().enumerate().fold(0, |index, (qubit, &bit)| {
+        if bit > 1 {
+            panic!("Bit {} of qubit {} is not 0 or 1.", bit, qubit);
+        }
+        index | (bit as i64) << qubit
+    })
+}
+
 impl Clone for QuReg<'_> {
     fn clone(&self) -> Self {
         unsafe {
             QuReg {
                 reg: ffi::createCloneQureg(self.reg, self.env.into()),
                 env: self.env,
+                profile: ProfileReport::default(),
             }
         }
     }
@@ -982,9 +3085,11 @@ impl Drop for QuReg<'_> {
     }
 }
 
+/// Hand over ownership of the register to the raw handle, which must then be
+/// destroyed with `destroyQureg`.
 impl From<QuReg<'_>> for ffi::Qureg {
     fn from(item: QuReg) -> Self {
-        item.reg
+        ManuallyDrop::new(item).reg
     }
 }
 
@@ -993,3 +3098,1803 @@ impl From<&QuReg<'_>> for ffi::Qureg {
         item.reg
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{
+        assert_qureg_close, basis_index_to_bits, bits_to_basis_index, estimate_state_vector_bytes,
+        memory_report, overlap_matrix, qureg_approx_eq, MemoryReport, QuReg,
+    };
+    use crate::{
+        ffi, ClassicalRegister, Complex, ComplexMatrix2, ComplexMatrix4, ComplexMatrixN,
+        EntanglerPattern, Outcome, QReal, QubitOrdering, QuestEnv, QuestError, DEFAULT_TOLERANCE,
+    };
+
+    #[test]
+    fn probability_of_state_is_not_amplitude() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+
+        let probability = qubits.probability_of_state(0b101);
+        assert!((probability - 0.125).abs() < 1e-10);
+
+        let amplitude = qubits.amplitude(0b101);
+        let expected_magnitude = 1.0 / (8.0 as QReal).sqrt();
+        assert!((amplitude.real - expected_magnitude).abs() < 1e-10);
+        assert!(amplitude.imag.abs() < 1e-10);
+    }
+
+    #[test]
+    fn params_string_reports_amplitudes() {
+        let env = QuestEnv::new();
+        let qubits = QuReg::new(10, &env);
+
+        let params = qubits.params_string();
+        assert!(params.contains("Number of qubits is 10."));
+        assert!(params.contains("Number of amps is 1024."));
+    }
+
+    #[test]
+    fn estimate_state_vector_bytes_for_ten_and_twenty_qubits() {
+        let size = std::mem::size_of::<QReal>() as u64;
+        assert_eq!(estimate_state_vector_bytes(10, false), 2 * 1024 * size);
+        assert_eq!(estimate_state_vector_bytes(20, false), 2 * 1_048_576 * size);
+        assert_eq!(estimate_state_vector_bytes(10, true), 2 * 1_048_576 * size);
+        assert_eq!(estimate_state_vector_bytes(40, true), u64::MAX);
+    }
+
+    #[test]
+    fn memory_report_splits_amplitudes_over_ranks() {
+        let size = std::mem::size_of::<QReal>() as u64;
+        let report = memory_report(30, 8, false);
+        assert_eq!(report.total_bytes, 2 * (1 << 30) * size);
+        // 2^27 amplitudes per rank, and a buffer of as many again.
+        assert_eq!(report.bytes_per_rank, 2 * 2 * (1 << 27) * size);
+        assert!(report.fits_in(report.bytes_per_rank));
+        assert!(!report.fits_in(report.bytes_per_rank - 1));
+
+        assert_eq!(
+            memory_report(10, 1, true),
+            MemoryReport {
+                total_bytes: estimate_state_vector_bytes(10, true),
+                bytes_per_rank: estimate_state_vector_bytes(10, true),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn memory_report_rejects_uneven_ranks() {
+        memory_report(10, 3, false);
+    }
+
+    #[test]
+    fn try_new_respects_memory_limit() {
+        let mut env = QuestEnv::new();
+        env.set_memory_limit(estimate_state_vector_bytes(10, false));
+
+        assert!(QuReg::try_new(10, &env).is_ok());
+        assert_eq!(
+            QuReg::try_new(11, &env).err(),
+            Some(QuestError::MemoryLimitExceeded {
+                required_bytes: estimate_state_vector_bytes(11, false),
+                limit_bytes: estimate_state_vector_bytes(10, false),
+            })
+        );
+    }
+
+    #[test]
+    fn try_new_rejects_invalid_registers() {
+        let env = QuestEnv::new();
+        assert!(QuReg::try_new(3, &env).is_ok());
+        assert!(QuReg::try_new_density(3, &env).unwrap().is_density_matrix());
+        assert_eq!(
+            QuReg::try_new(0, &env).err(),
+            Some(QuestError::InvalidQubitCount { num_qubits: 0 })
+        );
+        assert_eq!(
+            QuReg::try_new_density(-1, &env).err(),
+            Some(QuestError::InvalidQubitCount { num_qubits: -1 })
+        );
+        assert_eq!(
+            QuReg::try_new_density(40, &env).err(),
+            Some(QuestError::AllocationTooLarge {
+                required_bytes: u64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn multi_controlled_overlap_is_rejected() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        let pauli_x = ComplexMatrix2::real([[0.0, 1.0], [1.0, 0.0]]);
+        let swap = ComplexMatrix4::real([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let mut identity = ComplexMatrixN::new(2);
+        for i in 0..4 {
+            identity.set_real(i, i, 1.0);
+        }
+        let overlap = Err(QuestError::ControlTargetOverlap { qubit: 1 });
+
+        assert_eq!(
+            qubits
+                .try_multi_controlled_unitary(vec![0, 1], 1, pauli_x)
+                .map(|_| ()),
+            overlap
+        );
+        assert_eq!(
+            qubits
+                .try_multi_controlled_two_qubit_unitary(vec![1], 2, 1, swap)
+                .map(|_| ()),
+            overlap
+        );
+        assert_eq!(
+            qubits
+                .try_multi_controlled_multi_qubit_unitary(vec![1], vec![1, 2], &identity)
+                .map(|_| ()),
+            overlap
+        );
+        assert_eq!(
+            qubits
+                .try_multi_state_controlled_unitary(vec![0, 1], vec![1, 0], 1, pauli_x)
+                .map(|_| ()),
+            overlap
+        );
+
+        assert!(qubits
+            .try_multi_controlled_unitary(vec![0, 1], 2, pauli_x)
+            .is_ok());
+    }
+
+    #[test]
+    fn reduced_density_matrix_of_bell_state_is_maximally_mixed() {
+        let env = QuestEnv::new();
+        let mut bell = QuReg::new(2, &env);
+        bell.init_zero_state().hadamard(0).controlled_not(0, 1);
+        let mut bell_density = QuReg::new_density(2, &env);
+        bell_density.init_pure_state(&bell);
+
+        for source in &[bell, bell_density] {
+            let reduced = source.reduced_density_matrix(&[1], &env);
+            assert!(reduced.is_density_matrix());
+            assert!((reduced.calculate_purity() - 0.5).abs() < 1e-10);
+
+            let expected = [[0.5, 0.0], [0.0, 0.5]];
+            for row in 0..2 {
+                for column in 0..2 {
+                    let element = reduced.density_amplitude(row, column);
+                    assert!((element.real - expected[row as usize][column as usize]).abs() < 1e-10);
+                    assert!(element.imag.abs() < 1e-10);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reduced_density_matrix_keeps_qubit_order() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        // |q2 q1 q0> = |110>
+        qubits.init_classical_state(0b110);
+
+        let reduced = qubits.reduced_density_matrix(&[2, 0], &env);
+        // New qubit 0 is old qubit 2 (|1>), new qubit 1 is old qubit 0 (|0>).
+        assert!((reduced.density_amplitude(0b01, 0b01).real - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn entropy_of_pure_and_mixed_states() {
+        let env = QuestEnv::new();
+        let mut pure = QuReg::new(2, &env);
+        pure.init_zero_state().hadamard(0).rotate_y(1, 0.3);
+        assert_eq!(pure.linear_entropy(), 0.0);
+        assert_eq!(pure.von_neumann_entropy(), 0.0);
+
+        let mut pure_density = QuReg::new_density(2, &env);
+        pure_density.init_pure_state(&pure);
+        assert!(pure_density.linear_entropy().abs() < 1e-10);
+        assert!(pure_density.von_neumann_entropy().abs() < 1e-10);
+
+        pure.init_zero_state().hadamard(0).controlled_not(0, 1);
+        let maximally_mixed = pure.reduced_density_matrix(&[0], &env);
+        assert!((maximally_mixed.linear_entropy() - 0.5).abs() < 1e-10);
+        assert!((maximally_mixed.von_neumann_entropy() - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn iterate_until_stops_grover_at_convergence() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        qubits.init_plus_state();
+
+        let solution = 0b1011;
+        let iterations = qubits.iterate_until(
+            |qureg| {
+                qureg
+                    .pauli_x(2)
+                    .multi_controlled_z(&[0, 1, 2, 3])
+                    .pauli_x(2)
+                    .apply_grover_diffuser(&[0, 1, 2, 3]);
+            },
+            |qureg| qureg.probability_of_state(solution) > 0.95,
+            100,
+        );
+
+        // The optimal number of iterations is floor(π/4 * sqrt(16)) = 3.
+        assert!(iterations > 0 && iterations <= 3);
+        assert!(qubits.probability_of_state(solution) > 0.95);
+
+        let again = qubits.iterate_until(
+            |qureg| {
+                qureg.hadamard(0);
+            },
+            |qureg| qureg.probability_of_state(solution) > 0.95,
+            100,
+        );
+        assert_eq!(again, 0);
+    }
+
+    #[test]
+    fn hardware_efficient_ansatz_with_zero_parameters_is_identity() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        for &entangler in &[EntanglerPattern::Linear, EntanglerPattern::AllToAll] {
+            qubits
+                .init_zero_state()
+                .apply_hardware_efficient_ansatz(2, &[0.0; 12], entangler);
+            assert!((qubits.probability_of_state(0) - 1.0).abs() < 1e-12);
+        }
+
+        // One layer flipping qubit 0, which the linear CNOTs carry along.
+        let mut params = [0.0; 6];
+        params[0] = std::f64::consts::PI;
+        qubits.init_zero_state().apply_hardware_efficient_ansatz(
+            1,
+            &params,
+            EntanglerPattern::Linear,
+        );
+        assert!((qubits.probability_of_state(0b111) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic]
+    fn hardware_efficient_ansatz_rejects_wrong_parameter_count() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.apply_hardware_efficient_ansatz(2, &[0.0; 4], EntanglerPattern::Linear);
+    }
+
+    #[test]
+    fn apply_repeated_composes_rotations() {
+        let env = QuestEnv::new();
+        let theta = 0.37;
+        let mut repeated = QuReg::new(1, &env);
+        let mut single = QuReg::new(1, &env);
+        repeated.init_plus_state();
+        single.init_plus_state();
+
+        repeated.apply_repeated(3, |qureg| {
+            qureg.rotate_y(0, theta);
+        });
+        single.rotate_y(0, 3.0 * theta);
+
+        assert_qureg_close(&repeated, &single, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn conjugate_flips_imaginary_parts() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits
+            .init_zero_state()
+            .hadamard(0)
+            .apply_s_gate(0)
+            .rotate_x(1, 0.8);
+        let original = qubits.clone();
+
+        qubits.conjugate();
+        for index in 0..4 {
+            let (before, after) = (original.amplitude(index), qubits.amplitude(index));
+            assert_eq!(after.real, before.real);
+            assert_eq!(after.imag, -before.imag);
+        }
+        assert!(qubits.amplitude(0b01).imag < 0.0);
+
+        qubits.conjugate();
+        assert_qureg_close(&qubits, &original, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn overlap_matrix_of_orthonormal_basis_is_identity() {
+        let env = QuestEnv::new();
+        let mut basis = [
+            QuReg::new(2, &env),
+            QuReg::new(2, &env),
+            QuReg::new(2, &env),
+        ];
+        basis[0].init_plus_state();
+        basis[1].init_plus_state().pauli_z(0);
+        basis[2]
+            .init_plus_state()
+            .pauli_z(1)
+            .apply_s_gate(0)
+            .apply_s_gate(0);
+
+        let overlaps = overlap_matrix(&basis.iter().collect::<Vec<_>>());
+        for (i, row) in overlaps.iter().enumerate() {
+            for (j, overlap) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((overlap.real - expected).abs() < 1e-10);
+                assert!(overlap.imag.abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn overlap_matrix_is_hermitian() {
+        let env = QuestEnv::new();
+        let mut a = QuReg::new(1, &env);
+        let mut b = QuReg::new(1, &env);
+        a.init_zero_state().hadamard(0);
+        b.init_zero_state().rotate_x(0, 0.6);
+
+        let overlaps = overlap_matrix(&[&a, &b]);
+        assert!(overlaps[0][1].imag.abs() > 1e-3);
+        assert_eq!(overlaps[1][0].real, overlaps[0][1].real);
+        assert_eq!(overlaps[1][0].imag, -overlaps[0][1].imag);
+    }
+
+    #[test]
+    fn measure_with_rng_is_reproducible() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(1, &env);
+
+        let mut outcomes = Vec::new();
+        for seed in 0..2 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let outcomes_for_seed: Vec<i32> = (0..20)
+                .map(|_| qubits.init_plus_state().measure_with_rng(0, &mut rng))
+                .collect();
+            outcomes.push(outcomes_for_seed);
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        let repeated: Vec<i32> = (0..20)
+            .map(|_| qubits.init_plus_state().measure_with_rng(0, &mut rng))
+            .collect();
+
+        assert_eq!(outcomes[0], repeated);
+        assert_ne!(outcomes[0], outcomes[1]);
+        assert!(repeated.contains(&0) && repeated.contains(&1));
+
+        // The register must be left in the measured state.
+        let outcome = qubits.init_plus_state().measure_with_rng(0, &mut rng);
+        assert_eq!(qubits.calculate_probability_of_outcome(0, outcome), 1.0);
+    }
+
+    #[test]
+    fn qureg_approx_eq_ignores_global_phase() {
+        let env = QuestEnv::new();
+        let mut state = QuReg::new(2, &env);
+        state
+            .init_zero_state()
+            .rotate_y(0, 0.8)
+            .controlled_not(0, 1);
+        assert!(qureg_approx_eq(&state, &state, 1e-10));
+
+        // Rz(2π) = -I on the rotated qubit, a global phase of -1.
+        let mut phased = state.clone();
+        phased.rotate_z(1, 2.0 * std::f64::consts::PI);
+        assert!(qureg_approx_eq(&state, &phased, 1e-10));
+        assert_qureg_close(&state, &phased, 1e-10);
+
+        let mut density = QuReg::new_density(2, &env);
+        density.init_pure_state(&state);
+        assert!(qureg_approx_eq(&density, &density.clone(), 1e-10));
+        assert!(!qureg_approx_eq(&state, &density, 1e-10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Registers differ by")]
+    fn assert_qureg_close_fails_for_orthogonal_states() {
+        let env = QuestEnv::new();
+        let mut zero = QuReg::new(1, &env);
+        let mut one = QuReg::new(1, &env);
+        zero.init_zero_state();
+        one.init_classical_state(1);
+        assert!(!qureg_approx_eq(&zero, &one, 1e-10));
+
+        assert_qureg_close(&zero, &one, 1e-10);
+    }
+
+    #[test]
+    fn init_from_probabilities_encodes_distribution() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(1, &env);
+        qubits.init_from_probabilities(&[0.3, 0.7]);
+        assert!((qubits.calculate_probability_of_outcome(0, 0) - 0.3).abs() < 1e-10);
+        assert!((qubits.calculate_probability_of_outcome(0, 1) - 0.7).abs() < 1e-10);
+        assert!(qubits.amplitude(1).real > 0.0 && qubits.amplitude(1).imag == 0.0);
+
+        let total = qubits.init_from_unnormalised_probabilities(&[1.0, 3.0]);
+        assert_eq!(total, 4.0);
+        assert!((qubits.calculate_probability_of_outcome(0, 1) - 0.75).abs() < 1e-10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Probabilities sum to")]
+    fn init_from_probabilities_rejects_unnormalised_distribution() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(1, &env);
+        qubits.init_from_probabilities(&[0.5, 0.6]);
+    }
+
+    #[test]
+    fn bloch_vectors_of_standard_states() {
+        let env = QuestEnv::new();
+        let assert_bloch = |qubits: &QuReg, qubit: i32, expected: (QReal, QReal, QReal)| {
+            let bloch = qubits.bloch_vector(qubit);
+            assert!(
+                (bloch.x - expected.0).abs() < 1e-10
+                    && (bloch.y - expected.1).abs() < 1e-10
+                    && (bloch.z - expected.2).abs() < 1e-10,
+                "Bloch vector of qubit {} is {:?}, not {:?}",
+                qubit,
+                bloch,
+                expected
+            );
+        };
+
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_zero_state();
+        assert_bloch(&qubits, 0, (0.0, 0.0, 1.0));
+        qubits.hadamard(0);
+        assert_bloch(&qubits, 0, (1.0, 0.0, 0.0));
+        qubits.apply_s_gate(0);
+        assert_bloch(&qubits, 0, (0.0, 1.0, 0.0));
+
+        // Each qubit of a Bell pair is maximally mixed.
+        qubits.init_zero_state().hadamard(0).controlled_not(0, 1);
+        assert_bloch(&qubits, 1, (0.0, 0.0, 0.0));
+
+        let mut density = QuReg::new_density(1, &env);
+        density.init_zero_state().mix_depolarising(0, 0.75);
+        assert_bloch(&density, 0, (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn multi_controlled_z_negates_all_ones_amplitude() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+        let plus_amplitude = qubits.amplitude(0).real;
+
+        qubits.multi_controlled_z(&[0, 1, 2]);
+        for index in 0..8 {
+            let expected = if index == 0b111 {
+                -plus_amplitude
+            } else {
+                plus_amplitude
+            };
+            assert!((qubits.amplitude(index).real - expected).abs() < 1e-10);
+        }
+
+        let mut expected = QuReg::new(3, &env);
+        expected.init_plus_state().controlled_phase_flip(0, 2);
+        qubits.init_plus_state().controlled_z(2, 0);
+        assert_qureg_close(&qubits, &expected, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn grover_diffuser_reflects_about_mean() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        let probabilities = [0.1, 0.2, 0.3, 0.4];
+        qubits.init_from_probabilities(&probabilities);
+        let amplitudes: Vec<QReal> = probabilities.iter().map(|p| p.sqrt()).collect();
+        let mean = amplitudes.iter().sum::<QReal>() / 4.0;
+
+        // The diffuser applies I - 2|s><s|, so the reflection has a global
+        // phase of -1.
+        qubits.apply_grover_diffuser(&[0, 1]);
+        for (index, amplitude) in amplitudes.into_iter().enumerate() {
+            let reflected = qubits.amplitude(index as i64);
+            assert!((reflected.real + (2.0 * mean - amplitude)).abs() < 1e-10);
+            assert!(reflected.imag.abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Grover's diffuser needs at least one qubit.")]
+    fn grover_diffuser_rejects_empty_qubits() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.apply_grover_diffuser(&[]);
+    }
+
+    #[test]
+    fn local_amps_of_plus_state_are_uniform() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+
+        let real = qubits.local_amps_real();
+        let imag = qubits.local_amps_imag();
+        assert_eq!(real.len() as i64, qubits.num_amps_per_chunk());
+        assert_eq!(imag.len() as i64, qubits.num_amps_per_chunk());
+        let magnitude = (0.125 as QReal).sqrt();
+        assert!(real.iter().all(|amp| (amp - magnitude).abs() < 1e-12));
+        assert!(imag.iter().all(|amp| *amp == 0.0));
+    }
+
+    #[test]
+    fn editing_local_amps_in_place() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_plus_state().rotate_z(0, 0.4);
+
+        for amp in qubits.local_amps_imag_mut() {
+            *amp = 0.0;
+        }
+        qubits
+            .local_amps_real_mut()
+            .copy_from_slice(&[1.0, 1.0, 1.0, 0.0]);
+        qubits.mark_state_dirty().normalize();
+
+        assert!((qubits.calculate_total_probability() - 1.0).abs() < 1e-12);
+        for index in 0..3 {
+            assert!((qubits.probability_of_state(index) - 1.0 / 3.0).abs() < 1e-12);
+            assert_eq!(qubits.imag_amplitude(index), 0.0);
+        }
+        assert_eq!(qubits.probability_of_state(3), 0.0);
+    }
+
+    #[test]
+    fn num_amps_total_covers_whole_register() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(5, &env);
+        qubits.init_plus_state();
+        assert_eq!(qubits.num_amps_total(), 1 << 5);
+        assert!((qubits.calculate_total_probability() - 1.0).abs() < 1e-10);
+
+        let mut density = QuReg::new_density(3, &env);
+        density.init_plus_state();
+        assert_eq!(density.num_amps_total(), 1 << 6);
+        assert!((density.calculate_total_probability() - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn multi_qubit_unitary_rejects_mismatched_matrix() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_zero_state();
+
+        let identity = |num_qubits| {
+            let mut matrix = ComplexMatrixN::new(num_qubits);
+            for i in 0..1 << num_qubits {
+                matrix.set_real(i, i, 1.0);
+            }
+            matrix
+        };
+
+        let error = qubits
+            .try_multi_qubit_unitary(vec![0, 1, 2], identity(2))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            QuestError::MatrixSizeMismatch {
+                matrix_dim: 4,
+                num_target_qubits: 3
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Matrix is 4x4 but 3 target qubits need a 8x8 matrix"
+        );
+        assert!(qubits
+            .try_multi_controlled_multi_qubit_unitary(vec![0], vec![1], &identity(2))
+            .is_err());
+        assert!(qubits
+            .try_multi_qubit_unitary(vec![0, 1], identity(2))
+            .is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Matrix is 8x8 but 1 target qubits need a 2x2 matrix")]
+    fn controlled_multi_qubit_unitary_panics_on_mismatched_matrix() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.controlled_multi_qubit_unitary(0, vec![1], ComplexMatrixN::new(3));
+    }
+
+    #[test]
+    fn measure_qubits_of_ghz_state_are_correlated() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        for _ in 0..10 {
+            qubits
+                .init_zero_state()
+                .hadamard(0)
+                .controlled_not(0, 1)
+                .controlled_not(1, 2)
+                .controlled_not(2, 3);
+
+            let outcomes = qubits.measure_qubits(&[2, 0, 1]);
+            assert_eq!(outcomes.len(), 3);
+            assert!(outcomes.iter().all(|&outcome| outcome == outcomes[0]));
+            // The unmeasured qubit has collapsed along with the others.
+            let unmeasured = qubits.calculate_probability_of_outcome(3, outcomes[0]);
+            assert!((unmeasured - 1.0).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn init_ghz_state_and_bell_pair() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_ghz_state();
+        let half_root = std::f64::consts::FRAC_1_SQRT_2 as QReal;
+        for index in 0..8 {
+            let expected = if index == 0b000 || index == 0b111 {
+                half_root
+            } else {
+                0.0
+            };
+            assert!((qubits.amplitude(index).real - expected).abs() < 1e-10);
+        }
+
+        let mut bell = QuReg::new(3, &env);
+        bell.init_bell_pair(0, 2);
+        let mut expected = QuReg::new(3, &env);
+        expected.init_zero_state().set_amplitudes(
+            0,
+            vec![half_root, 0.0, 0.0, 0.0, 0.0, half_root],
+            vec![0.0; 6],
+        );
+        assert!((bell.calculate_fidelity(&expected) - 1.0).abs() < 1e-10);
+
+        let mut density = QuReg::new_density(3, &env);
+        density.init_bell_pair(0, 2);
+        assert!((density.calculate_fidelity(&expected) - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn init_w_state_has_single_excitations() {
+        let env = QuestEnv::new();
+        for num_qubits in 1..5 {
+            let mut qubits = QuReg::new(num_qubits, &env);
+            qubits.init_w_state();
+            let expected_amplitude = (1.0 / num_qubits as QReal).sqrt();
+            for index in 0..1i64 << num_qubits {
+                let expected = if index.count_ones() == 1 {
+                    expected_amplitude
+                } else {
+                    0.0
+                };
+                let amplitude = qubits.amplitude(index);
+                assert!((amplitude.real - expected).abs() < 1e-10);
+                assert!(amplitude.imag.abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn hermitian_evolution_matches_rotations() {
+        let env = QuestEnv::new();
+        let time = 0.37;
+        let mut expected = QuReg::new(1, &env);
+        let mut evolved = QuReg::new(1, &env);
+
+        // exp(-i Z t) = Rz(2t).
+        expected.init_plus_state().rotate_z(0, 2.0 * time);
+        evolved.init_plus_state().apply_hermitian_evolution(
+            0,
+            ComplexMatrix2::real([[1.0, 0.0], [0.0, -1.0]]),
+            time,
+        );
+        assert_qureg_close(&expected, &evolved, 1e-10);
+
+        // An identity offset only adds a global phase, so 2I + Y gives Ry(2t).
+        expected.init_zero_state().rotate_y(0, 2.0 * time);
+        let hamiltonian = ComplexMatrix2::new([[2.0, 0.0], [0.0, 2.0]], [[0.0, -1.0], [1.0, 0.0]]);
+        evolved
+            .init_zero_state()
+            .apply_hermitian_evolution(0, hamiltonian, time);
+        assert_qureg_close(&expected, &evolved, 1e-10);
+
+        expected.init_zero_state().rotate_x(0, 2.0 * time);
+        evolved.init_zero_state().apply_hermitian_evolution(
+            0,
+            ComplexMatrix2::real([[0.0, 1.0], [1.0, 0.0]]),
+            time,
+        );
+        assert_qureg_close(&expected, &evolved, 1e-10);
+    }
+
+    #[test]
+    fn qft_of_classical_state() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_classical_state(0b011).apply_qft(&[0, 1, 2]);
+
+        let norm = (8.0 as QReal).sqrt().recip();
+        for y in 0..8 {
+            let angle = 2.0 * std::f64::consts::PI * (3 * y) as QReal / 8.0;
+            let amplitude = qubits.amplitude(y);
+            assert!((amplitude.real - norm * angle.cos()).abs() < 1e-10);
+            assert!((amplitude.imag - norm * angle.sin()).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn inverse_qft_undoes_qft() {
+        let env = QuestEnv::new();
+        let mut original = QuReg::new(4, &env);
+        original
+            .init_zero_state()
+            .rotate_y(0, 0.3)
+            .rotate_x(1, 1.2)
+            .controlled_not(1, 3)
+            .rotate_z(3, 0.8)
+            .hadamard(2);
+
+        let mut transformed = original.clone();
+        transformed.apply_qft(&[3, 0, 2]);
+        assert!(!qureg_approx_eq(&original, &transformed, 1e-3));
+        transformed.apply_inverse_qft(&[3, 0, 2]);
+        assert_qureg_close(&original, &transformed, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn clones_own_independent_registers() {
+        let env = QuestEnv::new();
+        let mut original = QuReg::new(2, &env);
+        original.init_classical_state(0b01);
+
+        let mut copy = original.clone();
+        assert_ne!(original.reg.stateVec.real, copy.reg.stateVec.real);
+        assert_ne!(original.reg.stateVec.imag, copy.reg.stateVec.imag);
+
+        copy.pauli_x(1);
+        assert_eq!(original.probability_of_state(0b01), 1.0);
+        assert_eq!(copy.probability_of_state(0b11), 1.0);
+        drop(copy);
+        assert_eq!(original.probability_of_state(0b01), 1.0);
+    }
+
+    #[test]
+    fn converting_to_raw_handle_transfers_ownership() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(1, &env);
+        qubits.init_classical_state(1);
+
+        let raw: ffi::Qureg = qubits.into();
+        unsafe {
+            assert_eq!(ffi::getProbAmp(raw, 1), 1.0);
+            ffi::destroyQureg(raw, (&env).into());
+        }
+    }
+
+    #[test]
+    fn registers_can_move_between_threads() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+        assert_send::<QuReg>();
+        assert_sync::<QuestEnv>();
+
+        let env = QuestEnv::new();
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|worker| {
+                    let mut qubits = QuReg::new(3, &env);
+                    scope.spawn(move || {
+                        qubits.init_classical_state(worker).pauli_x(2);
+                        assert_eq!(qubits.measure(2), 1);
+                        qubits.probability_of_state(worker | 0b100)
+                    })
+                })
+                .collect();
+            for worker in workers {
+                assert_eq!(worker.join().unwrap(), 1.0);
+            }
+        });
+    }
+
+    #[test]
+    fn batched_pauli_products_match_individual_products() {
+        use crate::PauliOpType::{PauliX, PauliY, PauliZ};
+
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits
+            .init_zero_state()
+            .rotate_y(0, 0.4)
+            .rotate_x(1, 1.3)
+            .controlled_not(0, 2);
+
+        let terms = vec![
+            (vec![0], vec![PauliZ]),
+            (vec![1, 2], vec![PauliY, PauliZ]),
+            (vec![2, 0, 1], vec![PauliX, PauliX, PauliZ]),
+        ];
+        let mut workspace = QuReg::new(3, &env);
+        let batch = qubits.calculate_expected_pauli_products(&terms, &mut workspace);
+
+        assert_eq!(batch.len(), terms.len());
+        for ((targets, paulis), batched) in terms.into_iter().zip(batch) {
+            let individual =
+                qubits.calculate_expected_pauli_product(targets, paulis, QuReg::new(3, &env));
+            assert!((individual - batched).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn qft_from_phase_gradient_stages() {
+        let env = QuestEnv::new();
+        let mut expected = QuReg::new(3, &env);
+        expected
+            .init_zero_state()
+            .rotate_x(0, 0.9)
+            .rotate_y(1, 0.2)
+            .hadamard(2);
+        let mut actual = expected.clone();
+        expected.apply_qft(&[0, 1, 2]);
+
+        actual
+            .hadamard(2)
+            .apply_phase_gradient(&[2, 1, 0])
+            .hadamard(1)
+            .apply_phase_gradient(&[1, 0])
+            .hadamard(0)
+            .swap_gate(0, 2);
+        assert_qureg_close(&expected, &actual, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn apply_amp_fn_applies_custom_phases() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_plus_state();
+
+        // Multiply |x> by i^x.
+        qubits.apply_amp_fn(|index, amplitude| match index {
+            0 => amplitude,
+            1 => Complex::new(-amplitude.imag, amplitude.real),
+            2 => Complex::new(-amplitude.real, -amplitude.imag),
+            _ => Complex::new(amplitude.imag, -amplitude.real),
+        });
+        let expected = [(0.5, 0.0), (0.0, 0.5), (-0.5, 0.0), (0.0, -0.5)];
+        for (index, (real, imag)) in expected.iter().enumerate() {
+            let amplitude = qubits.amplitude(index as i64);
+            assert!((amplitude.real - real).abs() < 1e-12);
+            assert!((amplitude.imag - imag).abs() < 1e-12);
+        }
+
+        qubits.apply_amp_fn(|index, amplitude| {
+            if index == 0 {
+                Complex::zero()
+            } else {
+                amplitude
+            }
+        });
+        assert!((qubits.normalize().calculate_total_probability() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn normalize_after_projection() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        // Project |++> onto the subspace where qubit 0 is 0.
+        qubits
+            .init_plus_state()
+            .set_amplitudes(1, vec![0.0], vec![0.0])
+            .set_amplitudes(3, vec![0.0], vec![0.0]);
+        assert!(!qubits.is_normalized(1e-10));
+
+        qubits.normalize();
+        assert!(qubits.is_normalized(1e-10));
+        assert!((qubits.probability_of_state(0b10) - 0.5).abs() < 1e-10);
+
+        let mut density = QuReg::new_density(1, &env);
+        density.init_plus_state();
+        density.map_local_amplitudes(|index, amplitude| {
+            let factor = if index == 3 { 0.0 } else { 1.0 };
+            Complex::new(amplitude.real * factor, amplitude.imag * factor)
+        });
+        density.normalize();
+        assert!(density.is_normalized(1e-10));
+        assert!((density.density_amplitude(0, 0).real - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn controlled_phase_shifts_relative_phase_of_branches() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits
+            .init_zero_state()
+            .hadamard(1)
+            .controlled_phase(1, 0.6);
+
+        let (zero, one) = (qubits.amplitude(0b00), qubits.amplitude(0b10));
+        let relative_phase = one.imag.atan2(one.real) - zero.imag.atan2(zero.real);
+        assert!((relative_phase - 0.6).abs() < 1e-10);
+        assert!((qubits.probability_of_state(0b10) - 0.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn expectation_matrix_matches_pauli_expectation() {
+        use crate::PauliOpType::PauliZ;
+
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits
+            .init_zero_state()
+            .rotate_x(0, 0.5)
+            .rotate_y(2, 1.1)
+            .controlled_not(2, 1);
+        let mut workspace = QuReg::new(3, &env);
+
+        // Z on targets[0] and the identity on targets[1].
+        let mut z_first = ComplexMatrixN::new(2);
+        for i in 0..4 {
+            z_first.set_real(i, i, if i & 1 == 0 { 1.0 } else { -1.0 });
+        }
+        let expectation = qubits.expectation_matrix(&[2, 0], &z_first, &mut workspace);
+        let expected =
+            qubits.calculate_expected_pauli_product(vec![2], vec![PauliZ], QuReg::new(3, &env));
+        assert!((expectation.real - expected).abs() < 1e-10);
+        assert!(expectation.imag.abs() < 1e-10);
+
+        // <+|σ+|+> = 1/2 for the non-Hermitian σ+ = |0><1|.
+        let mut raising = ComplexMatrixN::new(1);
+        raising.set_real(0, 1, 1.0);
+        qubits.init_plus_state();
+        let expectation = qubits.expectation_matrix(&[1], &raising, &mut workspace);
+        assert!((expectation.real - 0.5).abs() < 1e-10);
+        assert!(expectation.imag.abs() < 1e-10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Qubit 0 is given more than once.")]
+    fn expectation_matrix_rejects_duplicate_targets() {
+        let env = QuestEnv::new();
+        let qubits = QuReg::new(2, &env);
+        let mut workspace = QuReg::new(2, &env);
+        qubits.expectation_matrix(&[0, 0], &ComplexMatrixN::new(2), &mut workspace);
+    }
+
+    #[test]
+    #[should_panic(expected = "Qubit 2 is outside of a register of 2 qubits.")]
+    fn expectation_matrix_rejects_out_of_range_targets() {
+        let env = QuestEnv::new();
+        let qubits = QuReg::new(2, &env);
+        let mut workspace = QuReg::new(2, &env);
+        qubits.expectation_matrix(&[2], &ComplexMatrixN::new(1), &mut workspace);
+    }
+
+    #[test]
+    fn init_mixed_ensemble_of_orthogonal_states() {
+        let env = QuestEnv::new();
+        let mut zero = QuReg::new(1, &env);
+        let mut one = QuReg::new(1, &env);
+        zero.init_zero_state();
+        one.init_classical_state(1);
+
+        let mut mixed = QuReg::new_density(1, &env);
+        mixed.init_mixed_ensemble(&[(0.5, &zero), (0.5, &one)]);
+        assert!((mixed.calculate_purity() - 0.5).abs() < 1e-10);
+        assert!((mixed.density_amplitude(0, 0).real - 0.5).abs() < 1e-10);
+        assert!(mixed.density_amplitude(0, 1).real.abs() < 1e-10);
+
+        // A single component gives the pure state, including its coherences.
+        let mut plus_i = QuReg::new(1, &env);
+        plus_i.init_zero_state().hadamard(0).apply_s_gate(0);
+        let mut expected = QuReg::new_density(1, &env);
+        expected.init_pure_state(&plus_i);
+        mixed.init_mixed_ensemble(&[(1.0, &plus_i)]);
+        assert_qureg_close(&expected, &mixed, 1e-10);
+    }
+
+    #[test]
+    fn ising_coupling_matches_multi_rotate_pauli() {
+        use crate::PauliOpType::PauliZ;
+
+        let env = QuestEnv::new();
+        let mut expected = QuReg::new(3, &env);
+        expected
+            .init_plus_state()
+            .rotate_y(0, 0.3)
+            .rotate_x(2, 1.1)
+            .apply_t_gate(0);
+        let mut actual = expected.clone();
+
+        expected.multi_rotate_pauli(vec![2, 0], vec![PauliZ, PauliZ], 0.7);
+        actual.ising_coupling(2, 0, 0.7);
+        assert_qureg_close(&expected, &actual, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn pauli_gadget_matches_multi_rotate_pauli() {
+        use crate::PauliOpType::{PauliI, PauliX, PauliY, PauliZ};
+
+        let env = QuestEnv::new();
+        let mut expected = QuReg::new(4, &env);
+        expected
+            .init_zero_state()
+            .rotate_y(0, 0.3)
+            .rotate_x(1, 1.7)
+            .hadamard(2)
+            .controlled_not(2, 3)
+            .apply_t_gate(3);
+        let mut actual = expected.clone();
+
+        let qubits = [3, 0, 1, 2];
+        let paulis = [PauliY, PauliX, PauliI, PauliZ];
+        expected.multi_rotate_pauli(qubits.to_vec(), paulis.to_vec(), 0.9);
+        actual.apply_pauli_gadget(&qubits, &paulis, 0.9);
+        assert_qureg_close(&expected, &actual, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn most_probable_state_after_grover_iterations() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        qubits.init_plus_state();
+        for _ in 0..3 {
+            // Mark |1010>.
+            qubits
+                .pauli_x(0)
+                .pauli_x(2)
+                .multi_controlled_z(&[0, 1, 2, 3])
+                .pauli_x(0)
+                .pauli_x(2)
+                .apply_grover_diffuser(&[0, 1, 2, 3]);
+        }
+
+        let (index, probability) = qubits.most_probable_state();
+        assert_eq!(index, 0b1010);
+        assert!(probability > 0.9);
+        assert!((qubits.calculate_total_probability() - 1.0).abs() < 1e-10);
+
+        let mut density = QuReg::new_density(2, &env);
+        density.init_classical_state(0b10);
+        assert_eq!(density.most_probable_state(), (0b10, 1.0));
+    }
+
+    #[test]
+    fn controlled_swap_only_swaps_when_control_is_set() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+
+        qubits.init_classical_state(0b010).controlled_swap(0, 1, 2);
+        assert_eq!(qubits.probability_of_state(0b010), 1.0);
+
+        qubits.init_classical_state(0b011).controlled_swap(0, 1, 2);
+        assert_eq!(qubits.probability_of_state(0b101), 1.0);
+
+        qubits.init_classical_state(0b111).controlled_swap(0, 1, 2);
+        assert_eq!(qubits.probability_of_state(0b111), 1.0);
+    }
+
+    #[test]
+    fn sdg_and_tdg_undo_s_and_t() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(1, &env);
+        qubits.init_plus_state().rotate_y(0, 0.2);
+        let initial = qubits.clone();
+
+        qubits.apply_s_gate(0).sdg(0);
+        assert_qureg_close(&qubits, &initial, DEFAULT_TOLERANCE);
+        qubits.apply_t_gate(0).tdg(0);
+        assert_qureg_close(&qubits, &initial, DEFAULT_TOLERANCE);
+
+        // T is a square root of S, so two Tdg gates undo an S.
+        qubits.apply_s_gate(0).tdg(0).tdg(0);
+        assert_qureg_close(&qubits, &initial, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn toffoli_only_flips_when_both_controls_are_set() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+
+        for state in 0..4 {
+            qubits.init_classical_state(state).toffoli(0, 1, 2);
+            let expected = if state == 0b11 { 0b111 } else { state };
+            assert_eq!(qubits.probability_of_state(expected), 1.0);
+        }
+
+        qubits
+            .init_classical_state(0b011)
+            .multi_controlled_not(&[0, 1], 2);
+        assert_eq!(qubits.probability_of_state(0b111), 1.0);
+    }
+
+    #[test]
+    fn profile_report_times_each_gate() {
+        let mut env = QuestEnv::new();
+        env.enable_profiling();
+        let mut qubits = QuReg::new(10, &env);
+        qubits.init_zero_state();
+
+        let mut matrix = ComplexMatrixN::new(2);
+        for i in 0..4 {
+            matrix.set_real(i, 3 - i, 1.0);
+        }
+        for qubit in 0..10 {
+            qubits.hadamard(qubit);
+        }
+        qubits.multi_qubit_unitary(vec![0, 1], matrix);
+
+        let report = qubits.profile_report();
+        let hadamard = report.get("hadamard").unwrap();
+        let unitary = report.get("multi_qubit_unitary").unwrap();
+        assert_eq!((hadamard.calls, unitary.calls), (10, 1));
+        assert!(hadamard.total > Duration::from_secs(0));
+        assert!(unitary.total > Duration::from_secs(0));
+        assert!(report.get("init_zero_state").is_none());
+
+        let unprofiled_env = QuestEnv::new();
+        let mut unprofiled = QuReg::new(1, &unprofiled_env);
+        unprofiled.hadamard(0);
+        assert!(unprofiled.profile_report().is_empty());
+    }
+
+    #[test]
+    fn apply_where_only_acts_on_selected_qubits() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(5, &env);
+        qubits.init_zero_state().apply_where(
+            |qubit| qubit % 2 == 0,
+            |qureg, qubit| {
+                qureg.pauli_x(qubit);
+            },
+        );
+        assert_eq!(qubits.probability_of_state(0b10101), 1.0);
+    }
+
+    #[test]
+    fn hadamard_all_gives_uniform_superposition() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_zero_state().hadamard_all();
+        for state in 0..8 {
+            assert!((qubits.probability_of_state(state) - 0.125).abs() < 1e-10);
+        }
+
+        qubits.init_zero_state().pauli_x_all();
+        assert_eq!(qubits.probability_of_state(0b111), 1.0);
+
+        qubits.init_zero_state().apply_to_all(|qureg, qubit| {
+            if qubit != 1 {
+                qureg.pauli_x(qubit);
+            }
+        });
+        assert_eq!(qubits.probability_of_state(0b101), 1.0);
+    }
+
+    #[test]
+    fn init_sparse_builds_bell_state() {
+        let env = QuestEnv::new();
+        let amplitude = Complex::new((0.5 as QReal).sqrt(), 0.0);
+        let mut sparse = QuReg::new(2, &env);
+        sparse.init_sparse(&[(0b00, amplitude), (0b11, amplitude)]);
+
+        let mut bell = QuReg::new(2, &env);
+        bell.init_bell_pair(0, 1);
+        assert!((sparse.calculate_fidelity(&bell) - 1.0).abs() < 1e-10);
+
+        sparse
+            .init_sparse(&[(1, Complex::new(3.0, 0.0)), (2, Complex::new(0.0, 4.0))])
+            .normalize();
+        assert!((sparse.probability_of_state(2) - 0.64).abs() < 1e-10);
+    }
+
+    #[test]
+    #[should_panic]
+    fn init_sparse_rejects_out_of_range_index() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_sparse(&[(4, Complex::new(1.0, 0.0))]);
+    }
+
+    #[test]
+    fn diagonal_and_marginals_of_density_matrix() {
+        let env = QuestEnv::new();
+        let mut mixed = QuReg::new_density(2, &env);
+        mixed
+            .init_plus_state()
+            .mix_depolarising(0, 0.75)
+            .mix_depolarising(1, 0.75);
+        for population in mixed.diagonal() {
+            assert!((population - 0.25).abs() < 1e-10);
+        }
+
+        let mut qubits = QuReg::new_density(3, &env);
+        qubits.init_classical_state(0b001).hadamard(2);
+        let marginals = qubits.marginal_probabilities(&[2, 0]);
+        let expected = [0.0, 0.0, 0.5, 0.5];
+        for (marginal, expected) in marginals.iter().zip(&expected) {
+            assert!((marginal - expected).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn unitary_dagger_undoes_unitary() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits
+            .init_zero_state()
+            .rotate_x(0, 0.4)
+            .controlled_not(0, 1);
+        let initial = qubits.clone();
+
+        let (c, s) = ((0.3 as QReal).cos(), (0.3 as QReal).sin());
+        let matrix = ComplexMatrix2::new([[c, 0.0], [0.0, c]], [[0.0, s], [s, 0.0]]);
+        qubits.unitary(1, matrix).unitary_dagger(1, matrix);
+        assert_qureg_close(&qubits, &initial, 1e-10);
+    }
+
+    #[test]
+    fn multi_rotate_z_weighted_matches_individual_rotations() {
+        let env = QuestEnv::new();
+        let mut weighted = QuReg::new(3, &env);
+        weighted
+            .init_plus_state()
+            .multi_rotate_z_weighted(&[0, 2], &[0.3, -1.1]);
+
+        let mut individual = QuReg::new(3, &env);
+        individual
+            .init_plus_state()
+            .rotate_z(0, 0.3)
+            .rotate_z(2, -1.1);
+        assert_qureg_close(&weighted, &individual, 1e-10);
+    }
+
+    #[test]
+    fn to_density_matrix_is_pure() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits
+            .init_zero_state()
+            .rotate_y(0, 1.2)
+            .controlled_not(0, 1);
+
+        let density = qubits.to_density_matrix(&env);
+        assert!(density.is_density_matrix());
+        assert!((density.calculate_purity() - 1.0).abs() < 1e-10);
+        for (population, state) in density.diagonal().iter().zip(0..) {
+            assert!((population - qubits.probability_of_state(state)).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn to_ndarray_matches_amplitudes() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_zero_state().hadamard(0).rotate_x(2, 0.6);
+
+        let amplitudes = qubits.to_ndarray();
+        assert_eq!(amplitudes.len() as i64, qubits.num_prob_amplitudes());
+        for index in 0..8 {
+            let (expected, actual) = (qubits.amplitude(index), amplitudes[index as usize]);
+            assert_eq!((actual.real, actual.imag), (expected.real, expected.imag));
+        }
+
+        let density = qubits.to_density_matrix(&env).to_ndarray_matrix();
+        assert_eq!(density.dim(), (8, 8));
+        let element = density[[1, 4]];
+        let expected = qubits.to_density_matrix(&env).density_amplitude(1, 4);
+        assert_eq!((element.real, element.imag), (expected.real, expected.imag));
+    }
+
+    #[test]
+    fn two_qubit_unitary_with_order_handles_endianness() {
+        let env = QuestEnv::new();
+        let mut cnot = [[0.0; 4]; 4];
+        cnot[0][0] = 1.0;
+        cnot[1][1] = 1.0;
+        cnot[2][3] = 1.0;
+        cnot[3][2] = 1.0;
+        let cnot = ComplexMatrix4::real(cnot);
+
+        let mut imported = QuReg::new(3, &env);
+        let mut expected = QuReg::new(3, &env);
+        for state in 0..8 {
+            imported
+                .init_classical_state(state)
+                .two_qubit_unitary_with_order(2, 0, cnot, QubitOrdering::BigEndian);
+            expected.init_classical_state(state).controlled_not(2, 0);
+            assert_qureg_close(&imported, &expected, 1e-10);
+
+            imported
+                .init_classical_state(state)
+                .two_qubit_unitary_with_order(0, 2, cnot, QubitOrdering::LittleEndian);
+            assert_qureg_close(&imported, &expected, 1e-10);
+        }
+    }
+
+    #[test]
+    fn noise_requires_density_matrix() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_zero_state();
+
+        let identity2 = ComplexMatrix2::real([[1.0, 0.0], [0.0, 1.0]]);
+        let mut identity4 = [[0.0; 4]; 4];
+        for (i, row) in identity4.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        let mut identity_n = ComplexMatrixN::new(1);
+        identity_n.set_real(0, 0, 1.0).set_real(1, 1, 1.0);
+
+        let results = vec![
+            qubits.try_mix_dephasing(0, 0.1).err(),
+            qubits.try_mix_two_qubit_dephasing(0, 1, 0.1).err(),
+            qubits.try_mix_depolarising(0, 0.1).err(),
+            qubits.try_mix_two_qubit_depolarising(0, 1, 0.1).err(),
+            qubits.try_mix_damping(0, 0.1).err(),
+            qubits.try_mix_pauli(0, 0.1, 0.1, 0.1).err(),
+            qubits
+                .try_mix_density_matrix(0.1, QuReg::new_density(2, &env))
+                .err(),
+            qubits.try_mix_kraus_map(0, vec![identity2]).err(),
+            qubits
+                .try_mix_two_qubit_kraus_map(0, 1, vec![ComplexMatrix4::real(identity4)])
+                .err(),
+            qubits
+                .try_mix_multi_qubit_kraus_map(vec![0], vec![identity_n])
+                .err(),
+        ];
+        for result in results {
+            assert_eq!(result, Some(QuestError::RequiresDensityMatrix));
+        }
+
+        let mut density = QuReg::new_density(2, &env);
+        density.init_zero_state();
+        assert!(density.try_mix_dephasing(0, 0.1).is_ok());
+        assert_eq!(
+            density
+                .try_mix_density_matrix(0.1, QuReg::new(2, &env))
+                .err(),
+            Some(QuestError::RequiresDensityMatrix)
+        );
+    }
+
+    #[test]
+    fn probability_of_bitstring_on_bell_state() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_bell_pair(0, 1);
+
+        assert!((qubits.probability_of_bitstring(&[0, 1], &[0, 0]) - 0.5).abs() < 1e-10);
+        assert!(qubits.probability_of_bitstring(&[0, 1], &[0, 1]).abs() < 1e-10);
+        assert!((qubits.probability_of_bitstring(&[1, 2], &[1, 0]) - 0.5).abs() < 1e-10);
+        assert!((qubits.probability_of_bitstring(&[], &[]) - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn t1_t2_noise_reduces_purity() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new_density(2, &env);
+        let infinite = [QReal::INFINITY; 2];
+        qubits
+            .init_plus_state()
+            .apply_t1_t2_noise(&infinite, &infinite, 1.0);
+        assert!((qubits.calculate_purity() - 1.0).abs() < 1e-10);
+
+        qubits
+            .init_plus_state()
+            .apply_t1_t2_noise(&[50.0, 80.0], &[30.0, 100.0], 1.0);
+        assert!(qubits.calculate_purity() < 1.0 - 1e-3);
+
+        // With only dephasing, the coherence of qubit 0 decays as exp(-t/T2).
+        let mut qubit = QuReg::new_density(1, &env);
+        qubit
+            .init_plus_state()
+            .apply_t1_t2_noise(&[QReal::INFINITY], &[10.0], 2.0);
+        let coherence = qubit.density_amplitude(0, 1).real;
+        assert!((coherence - 0.5 * (-0.2 as QReal).exp()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn distribution_distances() {
+        let env = QuestEnv::new();
+        let mut a = QuReg::new(2, &env);
+        let mut b = QuReg::new(2, &env);
+
+        a.init_plus_state();
+        b.init_plus_state();
+        assert!(a.total_variation_distance(&b).abs() < 1e-12);
+        assert!(a.kl_divergence(&b).abs() < 1e-12);
+
+        a.init_classical_state(0b01);
+        b.init_classical_state(0b10);
+        assert!((a.total_variation_distance(&b) - 1.0).abs() < 1e-12);
+        assert!(a.kl_divergence(&b).is_infinite());
+
+        // D(|0> || |+>) = ln 2 on one qubit.
+        let mut zero = QuReg::new(1, &env);
+        let mut plus = QuReg::new(1, &env);
+        zero.init_zero_state();
+        plus.init_plus_state();
+        assert!((zero.kl_divergence(&plus) - (2.0 as QReal).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn uhlmann_fidelity_of_mixed_states() {
+        let env = QuestEnv::new();
+        let mut pure = QuReg::new(2, &env);
+        let mut ensemble = QuReg::new_density(2, &env);
+        pure.init_plus_state().rotate_y(0, 0.4).controlled_not(0, 1);
+        ensemble.init_pure_state(&pure).mix_depolarising(1, 0.3);
+        assert!((ensemble.uhlmann_fidelity(&ensemble) - 1.0).abs() < 1e-8);
+
+        // Mixtures of |00>, |01> and of |10>, |11>.
+        let mut low = QuReg::new_density(2, &env);
+        let mut high = QuReg::new_density(2, &env);
+        low.init_zero_state().hadamard(0).mix_dephasing(0, 0.5);
+        high.init_classical_state(0b10)
+            .hadamard(0)
+            .mix_dephasing(0, 0.5);
+        assert!(low.uhlmann_fidelity(&high).abs() < 1e-8);
+        assert!((low.uhlmann_fidelity(&low) - 1.0).abs() < 1e-8);
+    }
+
+    #[test]
+    fn fix_global_phase_makes_states_identical() {
+        let env = QuestEnv::new();
+        let mut a = QuReg::new(2, &env);
+        a.init_zero_state()
+            .rotate_x(0, 0.9)
+            .hadamard(1)
+            .apply_t_gate(1);
+        let mut b = a.clone();
+        // XZXZ = -I, a global phase of π.
+        b.pauli_z(0).pauli_x(0).pauli_z(0).pauli_x(0);
+
+        a.fix_global_phase();
+        b.fix_global_phase();
+        for index in 0..4 {
+            let (x, y) = (a.amplitude(index), b.amplitude(index));
+            assert_eq!((x.real, x.imag), (y.real, y.imag));
+        }
+        let first = a.amplitude(0);
+        assert!(first.real > 0.0 && first.imag.abs() < 1e-12);
+
+        let mut c = QuReg::new(1, &env);
+        // Y|1> = -i|0>.
+        c.init_classical_state(1).pauli_y(0).rotate_z(0, 0.4);
+        c.fix_global_phase();
+        assert!((c.amplitude(0).real - 1.0).abs() < 1e-10);
+        assert!(c.amplitude(0).imag.abs() < 1e-12);
+    }
+
+    #[test]
+    fn random_pure_state_is_reproducible() {
+        let env = QuestEnv::new();
+        let mut a = QuReg::new(3, &env);
+        let mut b = QuReg::new(3, &env);
+        a.init_random_pure_state(&mut StdRng::seed_from_u64(7));
+        b.init_random_pure_state(&mut StdRng::seed_from_u64(7));
+
+        assert!((a.calculate_total_probability() - 1.0).abs() < 1e-10);
+        for index in 0..8 {
+            let (x, y) = (a.amplitude(index), b.amplitude(index));
+            assert_eq!((x.real, x.imag), (y.real, y.imag));
+        }
+
+        b.init_random_pure_state(&mut StdRng::seed_from_u64(8));
+        assert!(!qureg_approx_eq(&a, &b, 1e-3));
+    }
+
+    #[test]
+    fn measure_all_with_stats_of_basis_state() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(4, &env);
+        qubits.init_classical_state(0b1101);
+        let (outcomes, probability) = qubits.measure_all_with_stats();
+        assert_eq!(outcomes, vec![1, 0, 1, 1]);
+        assert!((probability - 1.0).abs() < 1e-10);
+
+        qubits.init_classical_state(0b0001).hadamard(3);
+        let (outcomes, probability) = qubits.measure_all_with_stats();
+        assert_eq!(outcomes[..3], [1, 0, 0]);
+        assert!((probability - 0.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn measure_result_matches_measure_with_stats() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_classical_state(0b10).rotate_y(0, 1.0);
+        let mut copy = qubits.clone();
+
+        let result = qubits.measure_result(1);
+        let (outcome, probability) = copy.measure_with_stats(1);
+        assert_eq!(result.outcome, outcome);
+        assert!((result.probability - probability).abs() < 1e-12);
+
+        let result = qubits.measure_result(0);
+        let zero_probability = (0.5 as QReal).cos().powi(2);
+        let expected = if result.outcome == 0 {
+            zero_probability
+        } else {
+            1.0 - zero_probability
+        };
+        assert!((result.probability - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn teleportation_with_classical_corrections() {
+        let env = QuestEnv::new();
+        let mut input = QuReg::new(1, &env);
+        input.init_zero_state().rotate_y(0, 0.7).rotate_z(0, 0.3);
+        let expected = input.bloch_vector(0);
+
+        for _ in 0..8 {
+            let mut qubits = QuReg::new(3, &env);
+            let mut bits = ClassicalRegister::new(2);
+            qubits
+                .init_zero_state()
+                .rotate_y(0, 0.7)
+                .rotate_z(0, 0.3)
+                .hadamard(1)
+                .controlled_not(1, 2)
+                .controlled_not(0, 1)
+                .hadamard(0);
+            qubits.measure_into(0, &mut bits, 0);
+            qubits.measure_into(1, &mut bits, 1);
+            qubits.x_if(&bits, 1, 2).z_if(&bits, 0, 2);
+
+            let teleported = qubits.bloch_vector(2);
+            assert!((teleported.x - expected.x).abs() < 1e-10);
+            assert!((teleported.y - expected.y).abs() < 1e-10);
+            assert!((teleported.z - expected.z).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn outcome_matches_integer_calls() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_zero_state().rotate_y(2, 0.8);
+
+        assert_eq!(
+            qubits.calculate_probability_of(2, Outcome::One),
+            qubits.calculate_probability_of_outcome(2, 1)
+        );
+        assert_eq!(
+            qubits.calculate_probability_of(2, Outcome::Zero),
+            qubits.calculate_probability_of_outcome(2, 0)
+        );
+
+        qubits.collapse_to(2, Outcome::One);
+        assert_eq!(qubits.measure_outcome(2), Outcome::One);
+        assert_eq!(qubits.measure_outcome(0), Outcome::Zero);
+    }
+
+    #[test]
+    fn apply_diagonal_fn_applies_maxcut_phase() {
+        let env = QuestEnv::new();
+        let edges = [(0, 1), (1, 2), (0, 2)];
+        let cut_size = |index: i64| {
+            edges
+                .iter()
+                .filter(|(a, b)| (index >> a) & 1 != (index >> b) & 1)
+                .count() as QReal
+        };
+
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+        let mut density = qubits.to_density_matrix(&env);
+        qubits.apply_diagonal_fn(|index| 0.4 * cut_size(index));
+
+        // |011> cuts edges (1, 2) and (0, 2).
+        let amplitude = qubits.amplitude(0b011);
+        let magnitude = (0.125 as QReal).sqrt();
+        assert!((amplitude.real - magnitude * (0.8 as QReal).cos()).abs() < 1e-10);
+        assert!((amplitude.imag - magnitude * (0.8 as QReal).sin()).abs() < 1e-10);
+        assert!((qubits.amplitude(0b111).imag).abs() < 1e-10);
+
+        density.apply_diagonal_fn(|index| 0.4 * cut_size(index));
+        assert_qureg_close(&density, &qubits.to_density_matrix(&env), 1e-10);
+    }
+
+    #[test]
+    fn controlled_diagonal_fn_only_acts_when_controls_are_set() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        for controls in 0..3 {
+            qubits.init_classical_state(controls).hadamard(2);
+            let initial = qubits.clone();
+            qubits.apply_controlled_diagonal_fn(&[0, 1], |_| 0.7);
+            assert_qureg_close(&qubits, &initial, 1e-10);
+        }
+
+        qubits.init_classical_state(0b011).hadamard(2);
+        let mut expected = qubits.clone();
+        qubits.apply_controlled_diagonal_fn(&[0, 1], |index| 0.5 * (index >> 2) as QReal);
+        expected.phase_shift(2, 0.5);
+        assert_qureg_close(&qubits, &expected, 1e-10);
+    }
+
+    #[test]
+    fn controlled_diagonal_fn_on_superposition_of_controls() {
+        let env = QuestEnv::new();
+        let phase = |index: i64| 0.3 * index as QReal;
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+        let mut density = qubits.to_density_matrix(&env);
+
+        qubits.apply_controlled_diagonal_fn(&[0, 2], phase);
+        let magnitude = (0.125 as QReal).sqrt();
+        for index in 0..8 {
+            let angle = if index & 0b101 == 0b101 {
+                phase(index)
+            } else {
+                0.0
+            };
+            let amplitude = qubits.amplitude(index);
+            assert!((amplitude.real - magnitude * angle.cos()).abs() < 1e-12);
+            assert!((amplitude.imag - magnitude * angle.sin()).abs() < 1e-12);
+        }
+
+        density.apply_controlled_diagonal_fn(&[0, 2], phase);
+        assert_qureg_close(&density, &qubits.to_density_matrix(&env), 1e-12);
+
+        let mut wrapped = QuReg::new(3, &env);
+        wrapped
+            .init_plus_state()
+            .apply_controlled_phase_fn(&[0, 2], phase);
+        assert_qureg_close(&wrapped, &qubits, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn phase_oracle_flips_only_marked_states() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_plus_state();
+        let mut density = qubits.to_density_matrix(&env);
+
+        let marked = [0b110, 0b011];
+        qubits.apply_phase_oracle(&marked);
+        let magnitude = (0.125 as QReal).sqrt();
+        for index in 0..8 {
+            let expected = if marked.contains(&index) {
+                -magnitude
+            } else {
+                magnitude
+            };
+            assert!((qubits.real_amplitude(index) - expected).abs() < 1e-12);
+            assert_eq!(qubits.imag_amplitude(index), 0.0);
+        }
+
+        density.apply_phase_oracle(&marked);
+        assert_qureg_close(&density, &qubits.to_density_matrix(&env), 1e-12);
+    }
+
+    #[test]
+    fn qaoa_layer_applies_cost_phase_and_mixer() {
+        let env = QuestEnv::new();
+        let cost = |index: i64| ((index & 1) ^ (index >> 1 & 1)) as QReal;
+
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_plus_state().rotate_y(0, 0.3);
+        let initial = qubits.clone();
+        qubits.apply_qaoa_layer(cost, 0.0, 0.0);
+        assert_qureg_close(&qubits, &initial, 1e-10);
+
+        qubits.apply_qaoa_layer(cost, 0.7, 0.0);
+        for index in 0..4 {
+            let (before, after) = (initial.amplitude(index), qubits.amplitude(index));
+            let angle = -0.7 * cost(index);
+            assert!(
+                (after.real - (before.real * angle.cos() - before.imag * angle.sin())).abs()
+                    < 1e-10
+            );
+            assert!(
+                (after.imag - (before.real * angle.sin() + before.imag * angle.cos())).abs()
+                    < 1e-10
+            );
+        }
+
+        let mut mixed = initial.clone();
+        mixed.rotate_x(0, 0.5).rotate_x(1, 0.5);
+        qubits = initial.clone();
+        qubits.apply_qaoa_layer(cost, 0.0, 0.25);
+        assert_qureg_close(&qubits, &mixed, 1e-10);
+    }
+
+    #[test]
+    fn basis_index_bit_conversion() {
+        assert_eq!(basis_index_to_bits(5, 3), vec![1, 0, 1]);
+        assert_eq!(basis_index_to_bits(1, 3), vec![1, 0, 0]);
+        assert_eq!(bits_to_basis_index(&[1, 0, 1]), 5);
+        assert_eq!(bits_to_basis_index(&[0, 0, 1]), 4);
+        for index in 0..16 {
+            assert_eq!(bits_to_basis_index(&basis_index_to_bits(index, 4)), index);
+        }
+    }
+
+    #[test]
+    fn marginal_state_of_bell_pair_is_maximally_mixed() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(2, &env);
+        qubits.init_bell_pair(0, 1);
+
+        let marginal = qubits.marginal_state(&[1], &env);
+        assert!(marginal.is_density_matrix());
+        assert_eq!(marginal.num_qubits(), 1);
+        assert!((marginal.calculate_purity() - 0.5).abs() < 1e-10);
+        assert!((marginal.density_amplitude(0, 0).real - 0.5).abs() < 1e-10);
+        assert!(marginal.density_amplitude(0, 1).real.abs() < 1e-10);
+    }
+
+    #[test]
+    fn controlled_multi_qubit_unitary_is_checked() {
+        let env = QuestEnv::new();
+        let mut qubits = QuReg::new(3, &env);
+        qubits.init_zero_state();
+
+        let mut swap = ComplexMatrixN::new(2);
+        swap.set_real(0, 0, 1.0)
+            .set_real(1, 2, 1.0)
+            .set_real(2, 1, 1.0)
+            .set_real(3, 3, 1.0);
+        assert_eq!(
+            qubits
+                .try_controlled_multi_qubit_unitary(0, vec![0, 1, 2], ComplexMatrixN::new(2))
+                .err(),
+            Some(QuestError::ControlTargetOverlap { qubit: 0 })
+        );
+        assert_eq!(
+            qubits
+                .try_controlled_multi_qubit_unitary(0, vec![1], ComplexMatrixN::new(2))
+                .err(),
+            Some(QuestError::MatrixSizeMismatch {
+                matrix_dim: 4,
+                num_target_qubits: 1
+            })
+        );
+
+        qubits.init_classical_state(0b011);
+        qubits
+            .try_controlled_multi_qubit_unitary(0, vec![1, 2], swap)
+            .unwrap();
+        assert_eq!(qubits.probability_of_state(0b101), 1.0);
+    }
+
+    #[test]
+    fn tensor_from_builds_product_state() {
+        let env = QuestEnv::new();
+        let mut plus = QuReg::new(1, &env);
+        plus.init_plus_state();
+        let mut product = QuReg::new(2, &env);
+        product.tensor_from(&[&plus, &plus]);
+
+        let mut expected = QuReg::new(2, &env);
+        expected.init_plus_state();
+        assert_qureg_close(&product, &expected, 1e-10);
+
+        let mut one = QuReg::new(1, &env);
+        one.init_classical_state(1);
+        let mut zeros = QuReg::new(2, &env);
+        zeros.init_zero_state();
+        let mut three = QuReg::new(3, &env);
+        three.tensor_from(&[&zeros, &one]);
+        assert_eq!(three.probability_of_state(0b100), 1.0);
+    }
+}